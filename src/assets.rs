@@ -0,0 +1,241 @@
+//! Static assets: every file in the site's `_static` directory, and every file
+//! other than Markdown in `content`, copied to the output with the same
+//! relative path. When fingerprinting, files from `_static` get a hash of
+//! their contents added to their names; files in `content` keep theirs, since
+//! their URLs are part of the site (think `robots.txt`, or a PDF which other
+//! sites link to).
+//!
+//! Pages and templates refer to assets by their *logical* path, relative to
+//! `_static` or `content` (e.g. `/fonts/body.woff2`), and the manifest maps
+//! that to the URL the file actually ends up at. Files the build generates,
+//! like compiled stylesheets, are added to the manifest the same way.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::config;
+use crate::page::markdown::links::{normalize, percent_decode};
+
+pub(crate) struct Manifest {
+    /// Each asset's URL, by its logical path (without a leading `/`).
+    urls: HashMap<String, String>,
+    /// Each asset's contents, and its path relative to the output directory.
+    files: Vec<(Contents, PathBuf)>,
+    /// Relative paths in pages are resolved against the page's location here.
+    content_dir: PathBuf,
+    fingerprint: bool,
+}
+
+enum Contents {
+    Copied(PathBuf),
+    Generated(String),
+}
+
+impl Manifest {
+    /// Find all the site's assets, and (if configured) fingerprint them.
+    pub(crate) fn new(config: &config::Assets, site_dir: &Path) -> Result<Manifest, String> {
+        let exclude = config
+            .exclude
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|e| format!("bad asset exclusion '{}': {}", pattern, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let static_dir = site_dir.join("_static");
+        let content_dir = site_dir.join("content");
+
+        let mut sources: HashMap<String, (PathBuf, bool)> = HashMap::new();
+        for (dir, is_content) in [(&static_dir, false), (&content_dir, true)] {
+            for path in files_in(dir)? {
+                if is_content && path.extension().is_some_and(|ext| ext == "md") {
+                    continue;
+                }
+
+                let logical = logical_path(
+                    path.strip_prefix(dir)
+                        .expect("everything found in a directory is in it"),
+                );
+                if exclude.iter().any(|pattern| pattern.matches(&logical)) {
+                    continue;
+                }
+
+                if let Some((existing, _)) =
+                    sources.insert(logical.clone(), (path.clone(), is_content))
+                {
+                    return Err(format!(
+                        "'{}' and '{}' would both be copied to '{}'",
+                        existing.display(),
+                        path.display(),
+                        logical
+                    ));
+                }
+            }
+        }
+
+        let files = sources
+            .into_par_iter()
+            .map(|(logical, (source, is_content))| {
+                let output = if config.fingerprint && !is_content {
+                    let bytes = std::fs::read(&source)
+                        .map_err(|e| format!("could not read '{}': {}", source.display(), e))?;
+                    fingerprinted(&logical, &bytes)
+                } else {
+                    logical.clone()
+                };
+                Ok((logical, source, output))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let urls = files
+            .iter()
+            .map(|(logical, _, output)| (logical.clone(), format!("/{}", output)))
+            .collect();
+        let files = files
+            .into_iter()
+            .map(|(_, source, output)| (Contents::Copied(source), PathBuf::from(output)))
+            .collect();
+
+        Ok(Manifest {
+            urls,
+            files,
+            content_dir,
+            fingerprint: config.fingerprint,
+        })
+    }
+
+    /// Add a file generated by the build at `logical`, fingerprinted like the
+    /// files from `_static`.
+    pub(crate) fn add(&mut self, logical: String, contents: String) -> Result<(), String> {
+        if self.urls.contains_key(&logical) {
+            return Err(format!(
+                "a generated file would overwrite the asset at '{}'",
+                logical
+            ));
+        }
+
+        let output = if self.fingerprint {
+            fingerprinted(&logical, contents.as_bytes())
+        } else {
+            logical.clone()
+        };
+        self.urls.insert(logical, format!("/{}", output));
+        self.files
+            .push((Contents::Generated(contents), PathBuf::from(output)));
+        Ok(())
+    }
+
+    /// The URL of the asset at `logical`, e.g. `fonts/body.woff2`, if there is
+    /// one. A leading `/` is optional.
+    pub(crate) fn url(&self, logical: &str) -> Option<&str> {
+        self.urls
+            .get(logical.trim_start_matches('/'))
+            .map(String::as_str)
+    }
+
+    /// Every asset's URL, by logical path.
+    pub(crate) fn urls(&self) -> &HashMap<String, String> {
+        &self.urls
+    }
+
+    /// The URL for `dest`, as written in the page at `source`, if it refers to
+    /// an asset. Relative paths are relative to the page; absolute ones to
+    /// the root of the site. Any query or fragment is kept.
+    pub(crate) fn resolve(&self, dest: &str, source: &Path) -> Option<String> {
+        // Anything with a scheme (`https:`, `mailto:`, ...) is external.
+        if dest.contains(':') || dest.starts_with("//") || dest.starts_with('#') {
+            return None;
+        }
+
+        let split = dest.find(&['?', '#'][..]).unwrap_or(dest.len());
+        let (path, suffix) = dest.split_at(split);
+        let path = percent_decode(path);
+        let logical = match path.strip_prefix('/') {
+            Some(from_root) => from_root.to_string(),
+            None => {
+                let path = normalize(&source.parent()?.join(path));
+                logical_path(path.strip_prefix(&self.content_dir).ok()?)
+            }
+        };
+
+        self.url(&logical).map(|url| format!("{}{}", url, suffix))
+    }
+
+    /// Copy every asset to `output_dir`.
+    pub(crate) fn copy(&self, output_dir: &Path) -> Result<(), String> {
+        self.files
+            .par_iter()
+            .map(|(contents, output)| {
+                let output = output_dir.join(output);
+                if let Some(parent) = output.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("{}: {}", parent.display(), e))?;
+                }
+                match contents {
+                    Contents::Copied(source) => std::fs::copy(source, &output)
+                        .map(|_| ())
+                        .map_err(|e| format!("could not copy '{}': {}", source.display(), e)),
+                    Contents::Generated(contents) => std::fs::write(&output, contents)
+                        .map_err(|e| format!("{}: {}", output.display(), e)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Every file under `dir`, skipping hidden files and directories. A site
+/// without the directory simply has no files there.
+fn files_in(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let pattern = dir.join("**/*").to_string_lossy().to_string();
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..glob::MatchOptions::new()
+    };
+    glob::glob_with(&pattern, options)
+        .map_err(|e| format!("bad glob '{}': {}", pattern, e))?
+        .filter_map(|entry| match entry {
+            Ok(path) if path.is_file() => Some(Ok(path)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.to_string())),
+        })
+        .collect()
+}
+
+/// A relative path, always with `/` separators, so that it is the same on
+/// every platform.
+fn logical_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Insert a hash of `bytes` before the extension in `logical`, so
+/// `fonts/body.woff2` becomes e.g. `fonts/body.3f2a9c01be.woff2`.
+fn fingerprinted(logical: &str, bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes)
+        .iter()
+        .take(5)
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    let (dir, name) = match logical.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name),
+        None => (String::new(), logical),
+    };
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => {
+            format!("{}{}.{}.{}", dir, stem, hash, extension)
+        }
+        _ => format!("{}{}.{}", dir, name, hash),
+    }
+}
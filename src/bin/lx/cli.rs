@@ -4,6 +4,7 @@
 use std::path::PathBuf;
 
 // Third party
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
 use clap::Clap;
 
 #[derive(Clap)]
@@ -21,6 +22,42 @@ pub(crate) enum Command {
     Build {
         /// The root of the site (if different from the current directory).
         site_directory: Option<PathBuf>,
+
+        /// Include drafts and scheduled pages, marked as such.
+        #[clap(long)]
+        drafts: bool,
+
+        /// Build the site as it will be on this date (`2021-06-01`) or at this
+        /// time (`2021-06-01T09:00:00-06:00`), publishing anything scheduled
+        /// by then.
+        #[clap(long, parse(try_from_str = parse_date))]
+        as_of: Option<DateTime<FixedOffset>>,
+    },
+
+    /// Check a Netlify `_redirects` file against the site.
+    #[clap(name = "check-redirects")]
+    CheckRedirects {
+        /// The root of the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
+
+        /// The file to check (if not the site's own `_redirects`).
+        #[clap(long)]
+        file: Option<PathBuf>,
+
+        /// Check against the site built with drafts and scheduled pages.
+        #[clap(long)]
+        drafts: bool,
+
+        /// Check against the site as it will be on this date or at this time.
+        #[clap(long, parse(try_from_str = parse_date))]
+        as_of: Option<DateTime<FixedOffset>>,
+    },
+
+    /// Check the microformats in the built site.
+    #[clap(name = "check-mf2")]
+    CheckMf2 {
+        /// The root of the site (if different from the current directory).
+        site_directory: Option<PathBuf>,
     },
 }
 
@@ -29,3 +66,16 @@ impl Command {
         Self::parse()
     }
 }
+
+/// A full RFC 3339 timestamp, or a date, meaning the end of that day locally.
+fn parse_date(input: &str) -> Result<DateTime<FixedOffset>, String> {
+    DateTime::parse_from_rfc3339(input).or_else(|_| {
+        let date = NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .map_err(|_| format!("'{}' is neither a date nor an RFC 3339 time", input))?;
+        Local
+            .from_local_datetime(&date.and_hms(23, 59, 59))
+            .earliest()
+            .map(DateTime::from)
+            .ok_or_else(|| format!("'{}' does not exist in the local time zone", input))
+    })
+}
@@ -9,6 +9,24 @@ fn main() -> Result<(), String> {
         .expect("Something is suuuuper borked: I cannot even get the current working directory!");
 
     match Command::cli() {
-        Command::Build { site_directory } => lightning::build(site_directory.unwrap_or(cwd)),
+        Command::Build {
+            site_directory,
+            drafts,
+            as_of,
+        } => lightning::build(
+            site_directory.unwrap_or(cwd),
+            lightning::BuildOptions { drafts, as_of },
+        ),
+        Command::CheckRedirects {
+            site_directory,
+            file,
+            drafts,
+            as_of,
+        } => lightning::check_redirects(
+            site_directory.unwrap_or(cwd),
+            file,
+            lightning::BuildOptions { drafts, as_of },
+        ),
+        Command::CheckMf2 { site_directory } => lightning::check_mf2(site_directory.unwrap_or(cwd)),
     }
 }
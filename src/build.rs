@@ -1,59 +1,311 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, FixedOffset, Local};
+use pulldown_cmark::escape::escape_html;
+use rayon::iter::Either;
 use rayon::prelude::*;
 use syntect::parsing::SyntaxSet;
 
+use crate::assets::Manifest;
 use crate::config::Config;
+use crate::graph::Graph;
+use crate::history::History;
+use crate::microformats;
+use crate::minify;
+use crate::page::markdown::citations::Citer;
+use crate::page::markdown::images::ImageProcessor;
+use crate::page::markdown::links::Index;
+use crate::page::markdown::shortcodes::Shortcodes;
 use crate::page::{Page, Source};
+use crate::previews::Previews;
+use crate::redirects::Redirects;
+use crate::search;
+use crate::sitemap::{self, Sitemap};
+use crate::styles;
 
-pub fn build(in_dir: PathBuf) -> Result<(), String> {
+/// How to treat pages which aren't published yet.
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    /// Whether to build drafts and scheduled pages too, marked as such.
+    pub drafts: bool,
+    /// When to build the site as of, if not now: pages dated later than this
+    /// are scheduled rather than published.
+    pub as_of: Option<DateTime<FixedOffset>>,
+}
+
+pub fn build(in_dir: PathBuf, options: BuildOptions) -> Result<(), String> {
+    let as_of = options.as_of.unwrap_or_else(|| Local::now().into());
     let in_dir = std::fs::canonicalize(in_dir).map_err(|e| e.to_string())?;
     let config_path = in_dir.join(PathBuf::from("_data/config.json5"));
     let config = Config::from_file(&config_path)?;
 
     let syntax_set = load_syntaxes();
+    let citer = Citer::new(&config.citations)?;
+    let mut assets = Manifest::new(&config.assets, &in_dir)?;
+    styles::compile(&config.styles, &in_dir, &mut assets)?;
+    let shortcodes = Shortcodes::load(&in_dir.join("_ui/shortcodes"), &assets)?;
+    let images = ImageProcessor::new(&config.images, &in_dir, &config.output);
+    let history = History::load(&config.git, &in_dir)?;
 
-    get_files_to_load(&in_dir)
+    // Unpublished pages are left out entirely, before they're rendered: no
+    // page, no redirects, no sitemap entry. When previewing them, they say so
+    // on the page.
+    let content_dir = in_dir.join("content");
+    let (loaded, unpublished): (Vec<_>, Vec<_>) = get_files_to_load(&in_dir)
         .into_par_iter()
         .map(|path| {
-            std::fs::read_to_string(&path)
+            let source = std::fs::read_to_string(&path)
                 .map(|contents| Source {
                     path: path.clone(),
                     contents,
                 })
-                .map_err(|e| format!("{}: {}", path.display(), e))
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            let metadata =
+                Page::metadata_for(&source, &content_dir, &config, history.dates(&source.path))
+                    .map_err(|e| format!("{}: {}", source.path.display(), e))?;
+            Ok((source, metadata))
         })
+        .partition_map(|result| match result {
+            Ok((source, metadata)) if !(options.drafts || metadata.is_published(&as_of)) => {
+                Either::Right((source, metadata))
+            }
+            result => Either::Left(result),
+        });
+    let (pages, errors): (Vec<Page>, Vec<String>) = loaded
+        .into_par_iter()
         .map(|result| {
-            result.and_then(|source| {
-                Page::new(&source, &in_dir.join("content"), &syntax_set)
-                    .map_err(|e| format!("{}: {}", source.path.display(), e))
+            result.and_then(|(source, metadata)| {
+                Page::new(
+                    &source,
+                    metadata,
+                    &content_dir,
+                    &syntax_set,
+                    &config,
+                    &citer,
+                    &shortcodes,
+                    &images,
+                    &assets,
+                )
+                .map(|mut page| {
+                    if !page.metadata.is_published(&as_of) {
+                        page.contents = unpublished_marker(&page) + &page.contents;
+                    }
+                    page
+                })
+                .map_err(|e| format!("{}: {}", source.path.display(), e))
             })
         })
-        .map(|result| {
-            result.and_then(|page| {
-                let path = page.path(&config.output).with_extension("html");
-                let containing_dir = path
-                    .parent()
-                    .ok_or_else(|| format!("{} should have a containing dir!", path.display()))?;
-                std::fs::create_dir_all(containing_dir)
-                    .map_err(|e| format!("{}: {}", path.display(), e.to_string()))?;
-                std::fs::write(&path, page.contents)
-                    .map_err(|e| format!("{}: {}", path.display(), e))
+        .partition_map(|result| match result {
+            Ok(page) => Either::Left(page),
+            Err(e) => Either::Right(e),
+        });
+
+    // Links between pages can only be resolved once we know every page's URL.
+    // Unpublished pages can't be linked to, but are still worth knowing about
+    // to say why.
+    let index = Index::new(&pages, &unpublished, &config);
+    let (mut pages, link_errors): (Vec<Page>, Vec<String>) =
+        pages
+            .into_par_iter()
+            .partition_map(|mut page| match index.resolve(&mut page) {
+                Ok(()) => Either::Left(page),
+                Err(e) => Either::Right(format!("{}: {}", page.source.display(), e)),
+            });
+
+    // Posts (pages whose header or photo dates them) without an image of
+    // their own get one made from the site's template, if it has one.
+    let preview_errors = match Previews::load(&config, &in_dir)? {
+        Some(previews) => pages
+            .par_iter_mut()
+            .filter(|page| page.social.image.is_none() && page.metadata.date().is_some())
+            .filter_map(|page| match previews.generate(page) {
+                Ok(url) => {
+                    page.social.image = Some(url);
+                    None
+                }
+                Err(e) => Some(format!("{}: {}", page.source.display(), e)),
             })
+            .collect::<Vec<_>>(),
+        None => Vec::new(),
+    };
+
+    // Pages still build without these, so they're only warnings.
+    for page in &pages {
+        for warning in &page.metadata.warnings {
+            eprintln!("warning: {}: {}", page.source.display(), warning);
+        }
+    }
+    // Social previews work without a description or an image, too; they're
+    // only missing on so many pages that listing them all would be noise.
+    for (missing, count) in [
+        (
+            "no summary to describe them",
+            pages
+                .iter()
+                .filter(|page| page.social.description.is_none())
+                .count(),
+        ),
+        (
+            "no image",
+            pages
+                .iter()
+                .filter(|page| page.social.image.is_none())
+                .count(),
+        ),
+    ] {
+        if count > 0 {
+            eprintln!(
+                "warning: {} of {} pages have {} for social previews",
+                count,
+                pages.len(),
+                missing
+            );
+        }
+    }
+
+    let graph = Graph::new(&pages, &config, &in_dir);
+    let mut backlinks = graph.backlinks();
+    for page in &mut pages {
+        page.backlinks = backlinks
+            .remove(page.url(&config).as_str())
+            .unwrap_or_default();
+    }
+
+    let graph_path = config.output.join("link-graph.json");
+    let graph_written = graph.to_json().and_then(|json| {
+        std::fs::write(&graph_path, json).map_err(|e| format!("{}: {}", graph_path.display(), e))
+    });
+
+    let assets_copied = assets.copy(&config.output);
+    let redirects_written =
+        Redirects::new(&pages, &config, &in_dir).and_then(|redirects| redirects.write(&config));
+    let sitemap_written = Sitemap::new(&pages, &config)
+        .write(&config)
+        .and_then(|()| sitemap::write_robots(&config, &in_dir, &assets));
+    let search_written = if config.search.enabled {
+        search::Index::new(&pages, &config).write(&config)
+    } else {
+        Ok(())
+    };
+    let archive_written = if config.archive.enabled {
+        write_archive(&pages, &config)
+    } else {
+        Ok(())
+    };
+
+    let written = pages
+        .into_par_iter()
+        .map(|page| {
+            let path = page.path(&config.output).with_extension("html");
+            let containing_dir = path
+                .parent()
+                .ok_or_else(|| format!("{} should have a containing dir!", path.display()))?;
+            std::fs::create_dir_all(containing_dir)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            let contents = document(
+                &page.social.title,
+                &page.social.html(),
+                &microformats::entry(&page, &config),
+            );
+            let contents = if config.minify.html {
+                minify::html(&contents, &config.minify)
+            } else {
+                contents
+            };
+            std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))
         })
-        .fold(
-            || Ok(()),
-            |so_far, result| match (so_far, result) {
-                (Ok(_), Ok(_)) => Ok(()),
-                (Err(s), Ok(_)) => Err(s),
-                (Ok(_), Err(e)) => Err(e),
-                (Err(s), Err(e)) => Err(s + &e),
-            },
-        )
-        .collect()
+        .fold(|| Ok(()), join_errors)
+        .collect::<Result<(), String>>();
+
+    errors
+        .into_iter()
+        .chain(link_errors)
+        .chain(preview_errors)
+        .map(Err)
+        .chain(std::iter::once(graph_written))
+        .chain(std::iter::once(assets_copied))
+        .chain(std::iter::once(redirects_written))
+        .chain(std::iter::once(sitemap_written))
+        .chain(std::iter::once(search_written))
+        .chain(std::iter::once(archive_written))
+        .fold(written, join_errors)
+}
+
+/// A complete HTML document around `head` and `body`, both already rendered.
+fn document(title: &str, head: &str, body: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, title).expect("writing to a String cannot fail");
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>{}</title>\n\
+         {}</head>\n\
+         <body>\n\
+         {}</body>\n\
+         </html>\n",
+        escaped, head, body
+    )
+}
+
+/// Write every post (every dated page), newest first, as an `h-feed`.
+fn write_archive(pages: &[Page], config: &Config) -> Result<(), String> {
+    let slug = config.archive.slug.trim_matches('/');
+    if let Some(page) = pages.iter().find(|page| page.metadata.slug == slug) {
+        return Err(format!(
+            "{}: '{}' is where the archive goes (set `archive.slug` to move it)",
+            page.source.display(),
+            slug
+        ));
+    }
+
+    let mut posts = pages
+        .iter()
+        .filter(|page| page.metadata.date().is_some())
+        .collect::<Vec<_>>();
+    posts.sort_by(|a, b| b.metadata.date().cmp(&a.metadata.date()));
+
+    let contents = document(
+        &config.archive.title,
+        "",
+        &microformats::feed(&config.archive.title, &posts, config),
+    );
+    let contents = if config.minify.html {
+        minify::html(&contents, &config.minify)
+    } else {
+        contents
+    };
+
+    let path = config.output.join(slug).with_extension("html");
+    if let Some(containing_dir) = path.parent() {
+        std::fs::create_dir_all(containing_dir)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn unpublished_marker(page: &Page) -> String {
+    match page.metadata.date() {
+        Some(date) if !page.metadata.draft => format!(
+            "<p class=\"unpublished\">Scheduled for <time datetime=\"{}\">{}</time></p>\n",
+            date.to_rfc3339(),
+            date.format("%B %-d, %Y")
+        ),
+        _ => String::from("<p class=\"unpublished\">Draft</p>\n"),
+    }
+}
+
+fn join_errors(so_far: Result<(), String>, result: Result<(), String>) -> Result<(), String> {
+    match (so_far, result) {
+        (Ok(_), Ok(_)) => Ok(()),
+        (Err(s), Ok(_)) => Err(s),
+        (Ok(_), Err(e)) => Err(e),
+        (Err(s), Err(e)) => Err(s + &e),
+    }
 }
 
-fn get_files_to_load(in_dir: &PathBuf) -> Vec<PathBuf> {
+pub(crate) fn get_files_to_load(in_dir: &Path) -> Vec<PathBuf> {
     let content_dir = in_dir.join("content");
     let content_glob = content_dir.to_string_lossy() + "/**/*.md";
 
@@ -1,20 +1,89 @@
+mod admonitions;
+mod archive;
+mod assets;
+mod citations;
 mod email;
+mod footnotes;
+mod git;
+mod headings;
+mod images;
+mod math;
+mod minify;
+mod photos;
+mod previews;
+mod redirects;
+mod search;
+mod styles;
+mod typography;
 
 use std::path::{Path, PathBuf};
 
 use serde_derive::Deserialize;
 
+pub use admonitions::Admonitions;
+pub use archive::Archive;
+pub use assets::Assets;
+pub use citations::Citations;
 use email::Email;
+pub use footnotes::Footnotes;
+pub use git::Git;
+pub use headings::Headings;
+pub use images::{ImageFormat, Images};
+pub use math::{Math, MathOutput};
+pub use minify::Minify;
+pub use photos::Photos;
+pub use previews::Previews;
+pub use redirects::Redirects;
+pub use search::Search;
+pub use styles::Styles;
+pub use typography::Typography;
+pub(crate) use typography::TypographyOverrides;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub(crate) url: String,
+    // Layouts will show these; nothing else needs them.
+    #[allow(dead_code)]
     pub(crate) repo: String,
     pub(crate) title: Title,
+    #[allow(dead_code)]
     pub(crate) subtitle: String,
+    #[allow(dead_code)]
     pub(crate) description: String,
     pub(crate) author: Author,
     pub(crate) output: PathBuf,
+    #[serde(default)]
+    pub(crate) typography: Typography,
+    #[serde(default)]
+    pub(crate) citations: Citations,
+    #[serde(default)]
+    pub(crate) math: Math,
+    #[serde(default)]
+    pub(crate) headings: Headings,
+    #[serde(default)]
+    pub(crate) footnotes: Footnotes,
+    #[serde(default)]
+    pub(crate) admonitions: Admonitions,
+    #[serde(default)]
+    pub(crate) images: Images,
+    #[serde(default)]
+    pub(crate) photos: Photos,
+    #[serde(default)]
+    pub(crate) assets: Assets,
+    #[serde(default)]
+    pub(crate) styles: Styles,
+    #[serde(default)]
+    pub(crate) minify: Minify,
+    #[serde(default)]
+    pub(crate) redirects: Redirects,
+    #[serde(default)]
+    pub(crate) git: Git,
+    #[serde(default)]
+    pub(crate) search: Search,
+    #[serde(default)]
+    pub(crate) previews: Previews,
+    #[serde(default)]
+    pub(crate) archive: Archive,
 }
 
 impl Config {
@@ -24,26 +93,65 @@ impl Config {
         let mut config: Config = json5::from_str(&data)
             .map_err(|e| format!("could not parse '{}':\n{}", &path.display(), e))?;
 
-        config.output = std::fs::canonicalize(
-            path.parent()
-                .ok_or_else(|| String::from("config file will have a parent dir"))?
-                .join(config.output),
-        )
-        .map_err(|e| e.to_string())?;
+        let config_dir = path
+            .parent()
+            .ok_or_else(|| String::from("config file will have a parent dir"))?;
+
+        config.output =
+            std::fs::canonicalize(config_dir.join(config.output)).map_err(|e| e.to_string())?;
+
+        config.citations.bibliography = config
+            .citations
+            .bibliography
+            .map(|bibliography| config_dir.join(bibliography));
+
+        if config.citations.style.ends_with(".csl") {
+            config.citations.style = config_dir
+                .join(&config.citations.style)
+                .to_string_lossy()
+                .to_string();
+        }
 
         Ok(config)
     }
 }
 
+#[cfg(test)]
+impl Config {
+    /// A small site, with everything else at its defaults, built to `output`.
+    pub(crate) fn for_tests(output: &Path) -> Config {
+        let mut config: Config = json5::from_str(
+            r#"{
+                url: "https://example.com",
+                repo: "https://github.com/example/site",
+                title: { normal: "Example", stylized: "<i>Example</i>" },
+                subtitle: "A site",
+                description: "A site for tests",
+                author: {
+                    name: "Jo Example",
+                    email: "jo@example.com",
+                    links: ["https://social.example/@jo", "mailto:jo@example.com"],
+                },
+                output: "out",
+            }"#,
+        )
+        .expect("test config is legit");
+        config.output = output.to_path_buf();
+        config
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Title {
-    normal: String,
-    stylized: String,
+    pub(crate) normal: String,
+    pub(crate) stylized: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Author {
     pub(crate) name: String,
+    // Only checked for being an address, until a feed or layout shows it.
+    #[allow(dead_code)]
     #[serde(deserialize_with = "Email::de_from_str")]
     pub(crate) email: Email,
     pub(crate) links: Vec<String>,
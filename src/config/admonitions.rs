@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use serde_derive::Deserialize;
+
+/// The kinds of admonition (`:::note` and the like) a site can use, by name.
+/// Configured kinds are added to, or replace, the defaults.
+#[derive(Deserialize, Debug)]
+#[serde(from = "HashMap<String, Admonition>")]
+pub struct Admonitions(HashMap<String, Admonition>);
+
+/// Settings for one kind of admonition.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Admonition {
+    /// A title to show at the top of every admonition of this kind, unless the
+    /// block supplies its own.
+    pub(crate) title: Option<String>,
+    /// The class of the wrapper, for styling. Defaults to the kind's name.
+    pub(crate) class: Option<String>,
+}
+
+impl Admonitions {
+    pub(crate) fn get(&self, kind: &str) -> Option<&Admonition> {
+        self.0.get(kind)
+    }
+}
+
+impl Default for Admonitions {
+    fn default() -> Self {
+        Admonitions(
+            ["note", "warning", "aside", "callout"]
+                .iter()
+                .map(|kind| (kind.to_string(), Admonition::default()))
+                .collect(),
+        )
+    }
+}
+
+impl From<HashMap<String, Admonition>> for Admonitions {
+    fn from(configured: HashMap<String, Admonition>) -> Self {
+        let mut admonitions = Admonitions::default();
+        admonitions.0.extend(configured);
+        admonitions
+    }
+}
@@ -0,0 +1,25 @@
+use serde_derive::Deserialize;
+
+/// Settings for the archive: one page listing every post, newest first, as an
+/// `h-feed` readers can follow.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Archive {
+    /// Whether to write the archive at all.
+    pub(crate) enabled: bool,
+    /// Where the archive goes, as a page's `permalink` would say. No page may
+    /// have the same one.
+    pub(crate) slug: String,
+    /// The name of the feed, as its heading.
+    pub(crate) title: String,
+}
+
+impl Default for Archive {
+    fn default() -> Self {
+        Archive {
+            enabled: true,
+            slug: String::from("archive"),
+            title: String::from("Archive"),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use serde_derive::Deserialize;
+
+/// Settings for copying the site's static files: everything in `_static`, and
+/// everything but Markdown in `content`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Assets {
+    /// Add a hash of each file's contents to its name, so that it can be
+    /// cached forever and still be replaced when it changes. Only applies to
+    /// `_static`: files in `content` keep their names.
+    pub(crate) fingerprint: bool,
+    /// Glob patterns for files not to copy, relative to `_static` or `content`,
+    /// e.g. `"**/*.njk"`.
+    pub(crate) exclude: Vec<String>,
+}
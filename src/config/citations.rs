@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use serde_derive::Deserialize;
+
+/// Settings for citation processing.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Citations {
+    /// A BibTeX (`.bib`) or CSL-JSON (`.json`) bibliography to use for every
+    /// page, relative to the config file. Pages may supply their own instead.
+    pub(crate) bibliography: Option<PathBuf>,
+
+    /// Either the name of one of the CSL styles bundled with the generator,
+    /// e.g. `chicago-notes` or `apa`, or the path to a `.csl` file relative to
+    /// the config file.
+    pub(crate) style: String,
+
+    /// The locale to render citations in, e.g. `en-US`. Defaults to the
+    /// style's own default locale.
+    pub(crate) locale: Option<String>,
+
+    /// A heading to put above the rendered bibliography, if any.
+    pub(crate) bibliography_title: Option<String>,
+}
+
+impl Default for Citations {
+    fn default() -> Self {
+        Citations {
+            bibliography: None,
+            style: String::from("chicago-notes"),
+            locale: None,
+            bibliography_title: None,
+        }
+    }
+}
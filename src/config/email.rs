@@ -9,8 +9,10 @@ lazy_static! {
     static ref EMAIL_RE: Regex = Regex::new(r"([^@]+)@([^@]+)").unwrap();
 }
 
+// Parsing is the point for now: it rejects anything which isn't an address.
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
-pub(super) struct Email {
+pub(crate) struct Email {
     /// The username, the bit before the `@`
     local: String,
     /// The email host, the bit after the `@`
@@ -0,0 +1,22 @@
+use serde_derive::Deserialize;
+
+/// Settings for rendering footnotes.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Footnotes {
+    /// Render footnotes as Tufte-style sidenotes next to their references
+    /// rather than as endnotes. Pages can override this with `sidenotes`.
+    pub(crate) sidenotes: bool,
+    /// The content of the links from each endnote back to its references.
+    pub(crate) back_reference: String,
+}
+
+impl Default for Footnotes {
+    fn default() -> Self {
+        Footnotes {
+            sidenotes: false,
+            // The variation selector keeps iOS from rendering it as an emoji.
+            back_reference: String::from("↩\u{FE0E}"),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use serde_derive::Deserialize;
+
+/// Settings for reading the site's git history.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Git {
+    /// Whether to date pages by their history: the first commit of a page is
+    /// when it was created, and the last commit which changed more than its
+    /// header is when it was updated, unless its header says otherwise. The
+    /// history never gives a page a publication date.
+    pub(crate) dates: bool,
+}
@@ -0,0 +1,23 @@
+use serde_derive::Deserialize;
+
+/// Settings for heading IDs and anchor links.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Headings {
+    /// Whether to add a self-link to every heading, for readers to copy.
+    pub(crate) anchors: bool,
+    /// The content of the self-link.
+    pub(crate) anchor_text: String,
+    /// The class of the self-link, for styling (e.g. only showing on hover).
+    pub(crate) anchor_class: String,
+}
+
+impl Default for Headings {
+    fn default() -> Self {
+        Headings {
+            anchors: false,
+            anchor_text: String::from("#"),
+            anchor_class: String::from("heading-anchor"),
+        }
+    }
+}
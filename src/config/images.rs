@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use serde_derive::Deserialize;
+
+/// Settings for the responsive versions generated for each local image.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Images {
+    /// The widths, in pixels, to generate. Images are never scaled up, so
+    /// widths larger than the original are replaced by the original's width.
+    pub(crate) widths: Vec<u32>,
+    /// Formats to offer alongside the original's, for browsers supporting them.
+    pub(crate) formats: Vec<ImageFormat>,
+    /// Encoding quality, from 0 to 100, for lossy formats.
+    pub(crate) quality: u8,
+    /// The `sizes` attribute for the generated markup: how wide the image will
+    /// be displayed, so browsers can pick a variant before layout.
+    pub(crate) sizes: String,
+    /// Where the generated images go, relative to the output directory. It is
+    /// also the path they are served from.
+    pub(crate) output_dir: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl Default for Images {
+    fn default() -> Self {
+        Images {
+            widths: vec![480, 960, 1440, 1920],
+            formats: vec![ImageFormat::Webp],
+            quality: 80,
+            sizes: String::from("100vw"),
+            output_dir: PathBuf::from("images"),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub(crate) fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::collections::HashMap;
+
+use serde_derive::Deserialize;
+
+/// Settings for rendering TeX math at build time.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Math {
+    /// What kind of markup to render math to.
+    pub(crate) output: MathOutput,
+    /// Custom TeX macros available to every page, e.g. `"\\RR": "\\mathbb{R}"`.
+    pub(crate) macros: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MathOutput {
+    /// Plain MathML, which browsers render natively.
+    #[default]
+    MathML,
+    /// KaTeX's HTML, which needs KaTeX's stylesheet and fonts, plus MathML for
+    /// accessibility.
+    Html,
+}
@@ -0,0 +1,10 @@
+use serde_derive::Deserialize;
+
+/// Settings for minifying each page's HTML once it's rendered.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Minify {
+    pub(crate) html: bool,
+    /// Also drop the quotes around attribute values which don't need them.
+    pub(crate) unquote_attributes: bool,
+}
@@ -0,0 +1,16 @@
+use serde_derive::Deserialize;
+
+/// Settings for pages backed by photos.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Photos {
+    /// Whether to keep where each photo was taken in its metadata. Pages can
+    /// strip it with `photo: { path: ..., gps: false }`.
+    pub(crate) gps: bool,
+}
+
+impl Default for Photos {
+    fn default() -> Self {
+        Photos { gps: true }
+    }
+}
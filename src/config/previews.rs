@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+use serde_derive::Deserialize;
+
+/// Settings for the preview images generated for posts which don't have one.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Previews {
+    /// The SVG template for the images, relative to the site directory. Sites
+    /// without one don't get generated previews.
+    pub(crate) template: PathBuf,
+    /// The fonts the template can use, relative to the site directory. Only
+    /// TrueType and OpenType fonts are loaded: system fonts never are, so the
+    /// images come out the same wherever the site is built.
+    pub(crate) fonts: PathBuf,
+    /// Where the images go, relative to the output directory. It is also the
+    /// path they are served from.
+    pub(crate) output_dir: PathBuf,
+}
+
+impl Default for Previews {
+    fn default() -> Self {
+        Previews {
+            template: PathBuf::from("_ui/preview.svg"),
+            fonts: PathBuf::from("_static/fonts"),
+            output_dir: PathBuf::from("previews"),
+        }
+    }
+}
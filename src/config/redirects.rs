@@ -0,0 +1,21 @@
+use serde_derive::Deserialize;
+
+/// Settings for the redirects generated from pages' `aliases`.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Redirects {
+    /// The HTTP status for the redirects.
+    pub(crate) status: u16,
+    /// Also write a page at each alias which redirects with a meta refresh,
+    /// for hosts which don't support `_redirects`.
+    pub(crate) stubs: bool,
+}
+
+impl Default for Redirects {
+    fn default() -> Self {
+        Redirects {
+            status: 301,
+            stubs: false,
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use serde_derive::Deserialize;
+
+/// Settings for the site's search index.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Search {
+    /// Whether to build the index at all.
+    pub(crate) enabled: bool,
+    /// Where the index and its script go, relative to the output directory.
+    /// It is also the path they are served from.
+    pub(crate) output_dir: PathBuf,
+    /// Words too common to be worth indexing.
+    pub(crate) stop_words: Vec<String>,
+}
+
+impl Default for Search {
+    fn default() -> Self {
+        Search {
+            enabled: true,
+            output_dir: PathBuf::from("search"),
+            stop_words: [
+                "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into",
+                "is", "it", "no", "not", "of", "on", "or", "such", "that", "the", "their", "then",
+                "there", "these", "they", "this", "to", "was", "will", "with",
+            ]
+            .iter()
+            .map(|word| word.to_string())
+            .collect(),
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use serde_derive::Deserialize;
+
+/// Settings for compiling the stylesheets in the site's `_ui/styles`.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Styles {
+    /// Whether to strip everything from the CSS that browsers don't need.
+    pub(crate) minify: bool,
+    /// Where the compiled stylesheets go, relative to the output directory.
+    /// It is also the path they are served from.
+    pub(crate) output_dir: PathBuf,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Styles {
+            minify: true,
+            output_dir: PathBuf::from("styles"),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use serde_derive::Deserialize;
+
+/// Site-wide settings for the typographic "smartening" pass run over the text
+/// of every page.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Typography {
+    /// Convert straight quotes to curly quotes (and apostrophes).
+    pub(crate) quotes: bool,
+    /// Convert `--` to an en dash and `---` to an em dash.
+    pub(crate) dashes: bool,
+    /// Convert `...` to an ellipsis.
+    pub(crate) ellipses: bool,
+    /// Insert a non-breaking space between a number and a following unit, e.g.
+    /// `10 km`, so the two never end up on separate lines.
+    pub(crate) units: bool,
+    /// Insert a non-breaking space after words of at most this many letters,
+    /// so that e.g. "a" or "of" never dangle at the end of a line. `0` turns
+    /// this off.
+    pub(crate) short_words: usize,
+    /// Wrap runs of capitals (abbreviations like "HTML" or "NASA") in an
+    /// `<abbr>` so they can be styled as small caps.
+    pub(crate) small_caps: bool,
+    /// The class to put on the `<abbr>` when `small_caps` is set.
+    pub(crate) small_caps_class: String,
+}
+
+impl Default for Typography {
+    fn default() -> Self {
+        Typography {
+            quotes: true,
+            dashes: true,
+            ellipses: true,
+            units: true,
+            short_words: 0,
+            small_caps: false,
+            small_caps_class: String::from("small-caps"),
+        }
+    }
+}
+
+/// Per-page overrides for the site-wide `Typography` settings. Anything left
+/// unset falls back to the site configuration.
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct TypographyOverrides {
+    quotes: Option<bool>,
+    dashes: Option<bool>,
+    ellipses: Option<bool>,
+    units: Option<bool>,
+    short_words: Option<usize>,
+    small_caps: Option<bool>,
+}
+
+impl Typography {
+    /// Apply a page's overrides (if any) on top of these settings.
+    pub(crate) fn with_overrides(&self, overrides: Option<&TypographyOverrides>) -> Typography {
+        let overrides = match overrides {
+            Some(overrides) => overrides,
+            None => return self.clone(),
+        };
+
+        Typography {
+            quotes: overrides.quotes.unwrap_or(self.quotes),
+            dashes: overrides.dashes.unwrap_or(self.dashes),
+            ellipses: overrides.ellipses.unwrap_or(self.ellipses),
+            units: overrides.units.unwrap_or(self.units),
+            short_words: overrides.short_words.unwrap_or(self.short_words),
+            small_caps: overrides.small_caps.unwrap_or(self.small_caps),
+            small_caps_class: self.small_caps_class.clone(),
+        }
+    }
+}
@@ -2,7 +2,7 @@ mod json;
 
 use std::convert::TryFrom;
 
-use lx_json_feed::{AuthorOptions, FeedItem, JSONFeed};
+use lx_json_feed::{AuthorOptions, JSONFeed};
 
 use crate::{config::Config, page::Page};
 
@@ -22,6 +22,8 @@ pub(crate) struct Feed<'a> {
 }
 
 impl<'a> Feed<'a> {
+    // The build doesn't write JSON feeds yet.
+    #[allow(dead_code)]
     pub(crate) fn new(title: String, site_config: &'a Config, items: &'a [Page]) -> Feed<'a> {
         Feed {
             title,
@@ -0,0 +1,118 @@
+//! The site's link graph: which pages link to which, built from the resolved
+//! internal links of every page.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_derive::Serialize;
+
+use crate::config::Config;
+use crate::page::Page;
+
+/// A page which links to another, for the "what links here" on the target.
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct Backlink {
+    pub(crate) url: String,
+    pub(crate) title: Option<String>,
+    /// The sentence containing the link.
+    pub(crate) context: String,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+#[derive(Serialize, Debug)]
+struct Node {
+    url: String,
+    title: Option<String>,
+    /// The source file, relative to the site's directory.
+    source: PathBuf,
+}
+
+#[derive(Serialize, Debug)]
+struct Edge {
+    /// The URL of the linking page.
+    from: String,
+    /// The URL of the linked page.
+    to: String,
+    fragment: Option<String>,
+    context: String,
+}
+
+impl Graph {
+    /// Build the graph from pages whose links have all been resolved.
+    /// `site_dir` is the root of the site, which sources are shown relative to.
+    pub(crate) fn new(pages: &[Page], config: &Config, site_dir: &Path) -> Graph {
+        let urls = pages
+            .iter()
+            .map(|page| (page.source.as_path(), page.url(config)))
+            .collect::<HashMap<_, _>>();
+
+        let mut nodes = pages
+            .iter()
+            .map(|page| Node {
+                url: urls[page.source.as_path()].clone(),
+                title: page.metadata.title().map(String::from),
+                source: page
+                    .source
+                    .strip_prefix(site_dir)
+                    .unwrap_or(&page.source)
+                    .to_path_buf(),
+            })
+            .collect::<Vec<_>>();
+        nodes.sort_unstable_by(|a, b| a.url.cmp(&b.url));
+
+        let urls = &urls;
+        let mut edges = pages
+            .iter()
+            .flat_map(|page| {
+                let from = &urls[page.source.as_path()];
+                page.links.iter().filter_map(move |link| {
+                    let to = urls.get(link.resolved.as_deref()?)?;
+                    Some(Edge {
+                        from: from.clone(),
+                        to: to.clone(),
+                        fragment: link.fragment.clone(),
+                        context: link.context.clone(),
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+        Graph { nodes, edges }
+    }
+
+    /// For each page, by URL, every other page linking to it, once each.
+    pub(crate) fn backlinks(&self) -> HashMap<&str, Vec<Backlink>> {
+        let titles = self
+            .nodes
+            .iter()
+            .map(|node| (node.url.as_str(), node.title.as_ref()))
+            .collect::<HashMap<_, _>>();
+
+        let mut backlinks: HashMap<&str, Vec<Backlink>> = HashMap::new();
+        for edge in self.edges.iter().filter(|edge| edge.from != edge.to) {
+            let to = backlinks.entry(edge.to.as_str()).or_default();
+            if to.iter().any(|seen| seen.url == edge.from) {
+                continue;
+            }
+
+            to.push(Backlink {
+                url: edge.from.clone(),
+                title: titles.get(edge.from.as_str()).copied().flatten().cloned(),
+                context: edge.context.clone(),
+            });
+        }
+
+        backlinks
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| format!("could not serialize link graph: {}", e))
+    }
+}
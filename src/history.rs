@@ -0,0 +1,188 @@
+//! Dates from the site's git history, for pages whose headers don't give them.
+//! Everything comes from the repository's own object database: nothing is
+//! fetched.
+//!
+//! A page was created when it was first committed (following renames), and
+//! updated when it was last committed with a change to its body. Commits which
+//! only touch the header, or only reflow the text, don't count.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, TimeZone};
+use git2::{Delta, DiffFindOptions, DiffOptions, Oid, Repository, Sort};
+
+use crate::config::Git;
+use crate::page::components::Components;
+
+/// When a page was first committed, and when it last changed in substance.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Dates {
+    pub(crate) created: DateTime<FixedOffset>,
+    pub(crate) updated: DateTime<FixedOffset>,
+}
+
+/// The dates of every page in the site's history, by their full paths.
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    dates: HashMap<PathBuf, Dates>,
+}
+
+impl History {
+    /// Read the history of the pages in `site_dir/content`, if the config asks
+    /// for it; otherwise, no page has any history.
+    pub(crate) fn load(config: &Git, site_dir: &Path) -> Result<History, String> {
+        if !config.dates {
+            return Ok(History::default());
+        }
+
+        let repo = Repository::discover(site_dir).map_err(|e| {
+            format!(
+                "could not open the git repository for '{}': {}",
+                site_dir.display(),
+                e
+            )
+        })?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| format!("the git repository for '{}' is bare", site_dir.display()))?
+            .to_path_buf();
+        let content_dir = site_dir.join("content");
+        let pathspec = content_dir.strip_prefix(&workdir).map_err(|_| {
+            format!(
+                "'{}' is not in {}",
+                content_dir.display(),
+                workdir.display()
+            )
+        })?;
+
+        let mut walk = repo.revwalk().map_err(git_error)?;
+        // Newer commits come first, so renames are seen before older history.
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+            .map_err(git_error)?;
+        walk.push_head().map_err(git_error)?;
+
+        let mut found: HashMap<PathBuf, (DateTime<FixedOffset>, Option<DateTime<FixedOffset>>)> =
+            HashMap::new();
+        // The current names of renamed pages, by their older names.
+        let mut renamed: HashMap<PathBuf, PathBuf> = HashMap::new();
+        // Pages whose first commit we've seen: anything older at the same
+        // path was a different page.
+        let mut added: HashSet<PathBuf> = HashSet::new();
+
+        for oid in walk {
+            let commit = repo
+                .find_commit(oid.map_err(git_error)?)
+                .map_err(git_error)?;
+            // A merge only brings in changes from commits we see anyway.
+            if commit.parent_count() > 1 {
+                continue;
+            }
+
+            let time = to_datetime(commit.time()).ok_or_else(|| {
+                format!(
+                    "could not read the git history: commit {} has a bad date",
+                    commit.id()
+                )
+            })?;
+            let tree = commit.tree().map_err(git_error)?;
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree().map_err(git_error)?),
+                Err(_) => None,
+            };
+
+            let mut options = DiffOptions::new();
+            options.pathspec(pathspec);
+            let mut diff = repo
+                .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), Some(&mut options))
+                .map_err(git_error)?;
+            diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+                .map_err(git_error)?;
+
+            for delta in diff.deltas() {
+                let path = match delta.new_file().path() {
+                    Some(path) if path.extension().is_some_and(|extension| extension == "md") => {
+                        path
+                    }
+                    _ => continue,
+                };
+                let current = renamed
+                    .get(path)
+                    .cloned()
+                    .unwrap_or_else(|| path.to_path_buf());
+                if added.contains(&current) {
+                    continue;
+                }
+
+                let substantive = match delta.status() {
+                    Delta::Added => {
+                        added.insert(current.clone());
+                        true
+                    }
+                    Delta::Modified => {
+                        changes_body(&repo, delta.old_file().id(), delta.new_file().id())
+                    }
+                    Delta::Renamed => {
+                        if let Some(old) = delta.old_file().path() {
+                            renamed.insert(old.to_path_buf(), current.clone());
+                        }
+                        changes_body(&repo, delta.old_file().id(), delta.new_file().id())
+                    }
+                    _ => continue,
+                };
+
+                let (created, updated) =
+                    found.entry(workdir.join(&current)).or_insert((time, None));
+                *created = (*created).min(time);
+                if substantive {
+                    *updated = Some(updated.map_or(time, |updated| updated.max(time)));
+                }
+            }
+        }
+
+        let dates = found
+            .into_iter()
+            .map(|(path, (created, updated))| {
+                let updated = updated.unwrap_or(created);
+                (path, Dates { created, updated })
+            })
+            .collect();
+        Ok(History { dates })
+    }
+
+    /// The dates of the page at `path`, if it has been committed.
+    pub(crate) fn dates(&self, path: &Path) -> Option<Dates> {
+        self.dates.get(path).copied()
+    }
+}
+
+/// Whether two versions of a page differ in more than their headers and
+/// whitespace. If either can't be read, assume they do.
+fn changes_body(repo: &Repository, old: Oid, new: Oid) -> bool {
+    let body = |oid: Oid| {
+        repo.find_blob(oid).ok().map(|blob| {
+            let text = String::from_utf8_lossy(blob.content());
+            let body = Components::try_from(text.as_ref())
+                .map(|components| components.body)
+                .unwrap_or(&text);
+            body.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+    };
+
+    match (body(old), body(new)) {
+        (Some(old), Some(new)) => old != new,
+        _ => true,
+    }
+}
+
+/// A commit's time, or `None` if its offset or timestamp is out of range.
+fn to_datetime(time: git2::Time) -> Option<DateTime<FixedOffset>> {
+    FixedOffset::east_opt(time.offset_minutes() * 60)?
+        .timestamp_opt(time.seconds(), 0)
+        .single()
+}
+
+fn git_error(e: git2::Error) -> String {
+    format!("could not read the git history: {}", e)
+}
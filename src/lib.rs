@@ -1,8 +1,20 @@
 //! Generate web sites from Markdown content and YAML configuration.
 
+mod assets;
 mod build;
 pub mod config;
 mod feed;
+mod graph;
+mod history;
+mod microformats;
+mod minify;
 pub mod page;
+mod previews;
+mod redirects;
+mod search;
+mod sitemap;
+mod styles;
 
-pub use build::build;
+pub use build::{build, BuildOptions};
+pub use microformats::check::check_mf2;
+pub use redirects::check::check_redirects;
@@ -0,0 +1,317 @@
+//! [Microformats]: the classes which let IndieWeb readers, and services like
+//! micro.blog, understand pages the way they understand feeds. Every page is
+//! written as an `h-entry`, by the author's `h-card`, and the archive lists
+//! every post as an `h-feed`.
+//!
+//! `lx check-mf2` parses the output back to make sure it all holds together.
+//!
+//! [Microformats]: https://microformats.org/wiki/microformats2
+
+pub(crate) mod check;
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+
+use crate::config::Config;
+use crate::page::markdown::headings::TocEntry;
+use crate::page::markdown::text::strip_tags;
+use crate::page::Page;
+
+/// The author's `h-card`, as a property of whatever contains it (e.g.
+/// `p-author`). Each of the author's links is marked `rel="me"`, so the site
+/// and those profiles can vouch for each other.
+pub(crate) fn card(config: &Config, property: &str) -> String {
+    let mut html = format!(
+        "<div class=\"{} h-card\"><a class=\"p-name u-url\" href=\"{}\">{}</a>",
+        property,
+        href(&config.url),
+        text(&config.author.name)
+    );
+    for link in &config.author.links {
+        let (property, name) = match link.strip_prefix("mailto:") {
+            Some(address) => ("u-email", address),
+            None => (
+                "u-url",
+                link.split("://")
+                    .nth(1)
+                    .unwrap_or(link)
+                    .trim_end_matches('/'),
+            ),
+        };
+        html.push_str(&format!(
+            " <a class=\"{}\" rel=\"me\" href=\"{}\">{}</a>",
+            property,
+            href(link),
+            text(name)
+        ));
+    }
+    html.push_str("</div>\n");
+    html
+}
+
+/// A page as an `h-entry`: its contents, and everything its header says
+/// about it (including its photo's EXIF data), with how long it takes to
+/// read, its table of contents, and the pages linking to it.
+pub(crate) fn entry(page: &Page, config: &Config) -> String {
+    let mut html = String::from("<article class=\"h-entry\">\n<header>\n");
+    if let Some(title) = page.metadata.title() {
+        html.push_str(&format!(
+            "<h1 class=\"p-name\">{}</h1>\n",
+            text(&strip_tags(title))
+        ));
+    }
+    html.push_str(&properties(page, config));
+    html.push_str(&photo(page));
+    html.push_str(&format!(
+        "<p class=\"reading-time\">{} min read</p>\n",
+        page.reading_time()
+    ));
+    html.push_str(&card(config, "p-author"));
+    html.push_str("</header>\n");
+    if let Some(toc) = page.toc.as_deref().filter(|toc| !toc.is_empty()) {
+        html.push_str(&format!(
+            "<nav class=\"toc\">{}</nav>\n",
+            TocEntry::html(toc)
+        ));
+    }
+    html.push_str("<div class=\"e-content\">\n");
+    html.push_str(&page.contents);
+    html.push_str("</div>\n");
+    html.push_str(&backlinks(page));
+    html.push_str("</article>\n");
+    html
+}
+
+/// What the camera recorded about the photo a page is about: the camera and
+/// exposure, and where it was taken as an `h-geo`.
+fn photo(page: &Page) -> String {
+    let exif = match &page.metadata.photo {
+        Some(photo) => &photo.exif,
+        None => return String::new(),
+    };
+
+    let mut html = String::new();
+    let gear = exif
+        .camera
+        .iter()
+        .chain(&exif.lens)
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let exposure = &exif.exposure;
+    let settings = [
+        exposure
+            .shutter
+            .as_ref()
+            .map(|shutter| format!("{} s", shutter)),
+        exposure.aperture.map(|aperture| format!("f/{}", aperture)),
+        exposure.iso.map(|iso| format!("ISO {}", iso)),
+        exposure.focal_length.map(|length| format!("{} mm", length)),
+    ]
+    .iter()
+    .flatten()
+    .cloned()
+    .collect::<Vec<_>>()
+    .join(", ");
+    let details = [gear, settings]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .map(|part| text(part))
+        .collect::<Vec<_>>()
+        .join(" · ");
+    if !details.is_empty() {
+        html.push_str(&format!("<p class=\"photo-details\">{}</p>\n", details));
+    }
+
+    if let Some(gps) = &exif.gps {
+        html.push_str(&format!(
+            "<p class=\"p-location h-geo\"><data class=\"p-latitude\" value=\"{0}\">{0:.4}</data>, \
+             <data class=\"p-longitude\" value=\"{1}\">{1:.4}</data>",
+            gps.latitude, gps.longitude
+        ));
+        if let Some(altitude) = gps.altitude {
+            html.push_str(&format!(
+                ", <data class=\"p-altitude\" value=\"{0}\">{0:.0} m</data>",
+                altitude
+            ));
+        }
+        html.push_str("</p>\n");
+    }
+    html
+}
+
+/// The pages linking to this one, each with the sentence the link is in.
+fn backlinks(page: &Page) -> String {
+    if page.backlinks.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<nav class=\"backlinks\">\n<h2>Links to this page</h2>\n<ul>\n");
+    for backlink in &page.backlinks {
+        let title = backlink
+            .title
+            .as_deref()
+            .map(strip_tags)
+            .unwrap_or_else(|| backlink.url.clone());
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            href(&backlink.url),
+            text(&title)
+        ));
+        if !backlink.context.is_empty() {
+            html.push_str(&format!(": {}", text(&backlink.context)));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n</nav>\n");
+    html
+}
+
+/// Pages as an `h-feed` named `name`: each one an `h-entry` with its title
+/// and summary, linking to the page itself.
+pub(crate) fn feed(name: &str, pages: &[&Page], config: &Config) -> String {
+    let mut html = format!(
+        "<section class=\"h-feed\">\n<h1 class=\"p-name\">{}</h1>\n",
+        text(name)
+    );
+    html.push_str(&card(config, "p-author"));
+    for page in pages {
+        html.push_str("<article class=\"h-entry\">\n");
+        html.push_str(&format!(
+            "<h2 class=\"p-name\"><a href=\"{}\">{}</a></h2>\n",
+            href(&page.url(config)),
+            text(&page.social.title)
+        ));
+        html.push_str(&properties(page, config));
+        if let Some(summary) = &page.summary {
+            html.push_str(&format!(
+                "<div class=\"p-summary\">{}</div>\n",
+                summary.html.trim()
+            ));
+        }
+        html.push_str("</article>\n");
+    }
+    html.push_str("</section>\n");
+    html
+}
+
+/// The permalink, dates, and tags of a page.
+fn properties(page: &Page, config: &Config) -> String {
+    let url = href(&page.url(config));
+    let mut html = match page.metadata.date() {
+        Some(date) => format!(
+            "<p><a class=\"u-url u-uid\" href=\"{}\"><time class=\"dt-published\" datetime=\"{}\">{}</time></a>",
+            url,
+            date.to_rfc3339(),
+            date.format("%B %-d, %Y")
+        ),
+        None => format!("<p><a class=\"u-url u-uid\" href=\"{}\">Permalink</a>", url),
+    };
+    if let Some(updated) = &page.metadata.updated {
+        html.push_str(&format!(
+            ", updated <time class=\"dt-updated\" datetime=\"{}\">{}</time>",
+            updated.to_rfc3339(),
+            updated.format("%B %-d, %Y")
+        ));
+    }
+    html.push_str("</p>\n");
+
+    if !page.metadata.tags.is_empty() {
+        let tags = page
+            .metadata
+            .tags
+            .iter()
+            .map(|tag| format!("<span class=\"p-category\">{}</span>", text(tag)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        html.push_str(&format!("<p>{}</p>\n", tags));
+    }
+    html
+}
+
+fn text(value: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, value).expect("writing to a String cannot fail");
+    escaped
+}
+
+fn href(url: &str) -> String {
+    let mut escaped = String::new();
+    escape_href(&mut escaped, url).expect("writing to a String cannot fail");
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::check::check;
+    use super::*;
+    use crate::graph::Backlink;
+    use crate::page::markdown::summary::Summary;
+    use crate::page::social::Social;
+    use crate::page::Source;
+
+    fn page(config: &Config) -> Page {
+        let source = Source {
+            path: PathBuf::from("/site/content/reviews/tom-and-jerry.md"),
+            contents: String::from(
+                "---\n\
+                 title: \"Tom & Jerry: a <em>review</em>\"\n\
+                 date: 2024-03-01T09:00:00-05:00\n\
+                 updated: 2024-03-05T10:00:00-05:00\n\
+                 tags: [cartoons, \"cats & mice\"]\n\
+                 summary: \"A *classic*, reconsidered.\"\n\
+                 ---\n",
+            ),
+        };
+        let metadata = Page::metadata_for(&source, Path::new("/site/content"), config, None)
+            .expect("test page header is legit");
+        let summary = metadata.summary.as_deref().map(Summary::from_markdown);
+        let social = Social::new(
+            &metadata,
+            summary.as_ref(),
+            String::from("https://example.com/reviews/tom-and-jerry"),
+            None,
+            config,
+        );
+        Page {
+            metadata,
+            contents: String::from(
+                "<p>Write to <a href=\"mailto:jo@example.com\">me</a>, or read \
+                 <a href=\"https://example.org/cartoons?a=1&amp;b=2\">more</a>.</p>\n",
+            ),
+            text: String::new(),
+            toc: None,
+            source: source.path,
+            links: Vec::new(),
+            backlinks: vec![Backlink {
+                url: String::from("https://example.com/reviews/index"),
+                title: Some(String::from("All <em>reviews</em>")),
+                context: String::from("Start with Tom & Jerry."),
+            }],
+            summary,
+            words: 9,
+            social,
+        }
+    }
+
+    #[test]
+    fn output_is_well_formed() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        let page = page(&config);
+
+        // Both include the author's card, with its `mailto:` and https links.
+        let entry = entry(&page, &config);
+        assert!(entry.contains("<span class=\"p-category\">cats &amp; mice</span>"));
+        assert!(entry.contains("<a class=\"u-email\" rel=\"me\" href=\"mailto:jo@example.com\">"));
+        assert!(entry.contains(
+            "<li><a href=\"https://example.com/reviews/index\">All reviews</a>: Start with Tom &amp; Jerry.</li>"
+        ));
+        assert_eq!(check(&entry), Vec::<String>::new());
+
+        let feed = feed("Reviews", &[&page, &page], &config);
+        assert!(feed
+            .contains("<div class=\"p-summary\"><p>A <em>classic</em>, reconsidered.</p></div>"));
+        assert_eq!(check(&feed), Vec::<String>::new());
+    }
+}
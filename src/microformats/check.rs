@@ -0,0 +1,280 @@
+//! `lx check-mf2`: parse the built site the way IndieWeb readers do, and find
+//! the microformats they would misread or drop: properties outside any
+//! microformat, URLs and dates which don't parse, and entries and cards
+//! missing what identifies them.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use regex::Regex;
+use scraper::{ElementRef, Html};
+
+use crate::config::Config;
+
+lazy_static! {
+    /// A microformats class name: a prefix, then an optional vendor prefix,
+    /// then lowercase words. Anything else (`h-4`, `p-Name`) isn't one, and
+    /// parsers ignore it.
+    static ref CLASS_NAME: Regex = Regex::new(r"^(h|p|u|dt|e)-([a-z0-9]+-)?[a-z]+(-[a-z]+)*$")
+        .expect("microformats class name regex is legit");
+}
+
+/// Check every page the site in `in_dir` has built, printing every problem
+/// found.
+pub fn check_mf2(in_dir: PathBuf) -> Result<(), String> {
+    let in_dir = std::fs::canonicalize(in_dir).map_err(|e| e.to_string())?;
+    let config = Config::from_file(&in_dir.join("_data/config.json5"))?;
+
+    let pattern = config.output.join("**/*.html");
+    let pattern = pattern.to_string_lossy();
+    let files = glob::glob(&pattern)
+        .map_err(|e| format!("bad glob '{}': {}", pattern, e))?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        return Err(format!(
+            "no pages in '{}': build the site first",
+            config.output.display()
+        ));
+    }
+
+    let problems = files
+        .par_iter()
+        .flat_map_iter(|path| {
+            let name = path
+                .strip_prefix(&config.output)
+                .unwrap_or(path)
+                .display()
+                .to_string();
+            match std::fs::read_to_string(path) {
+                Ok(html) => check(&html)
+                    .into_iter()
+                    .map(|problem| format!("{}: {}", name, problem))
+                    .collect::<Vec<_>>(),
+                Err(e) => vec![format!("{}: {}", name, e)],
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let output = config.output.display();
+    if problems.is_empty() {
+        println!("{}: {} pages, all fine", output, files.len());
+        Ok(())
+    } else {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        Err(format!(
+            "found {} problem{} in '{}'",
+            problems.len(),
+            if problems.len() == 1 { "" } else { "s" },
+            output
+        ))
+    }
+}
+
+/// The problems with the microformats in one page.
+pub(super) fn check(html: &str) -> Vec<String> {
+    let document = Html::parse_document(html);
+    let mut problems = Vec::new();
+
+    for element in document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+    {
+        let types = classes(element, true);
+        let properties = classes(element, false);
+
+        if !properties.is_empty() && root_of(element).is_none() {
+            problems.push(format!(
+                "{} is not inside any microformat, so nothing will read it",
+                describe(element)
+            ));
+        }
+
+        // A nested microformat's value is the microformat, not a URL or a
+        // date, so there's nothing more to check for those.
+        for property in properties.iter().filter(|_| types.is_empty()) {
+            if property.starts_with("u-") {
+                if let Some(attribute) = url_attribute(element) {
+                    if element
+                        .attr(attribute)
+                        .is_none_or(|url| url.trim().is_empty())
+                    {
+                        problems.push(format!(
+                            "{} has no '{}' for its URL",
+                            describe(element),
+                            attribute
+                        ));
+                    }
+                }
+            } else if property.starts_with("dt-") {
+                let value = date_value(element);
+                if !is_date(&value) {
+                    problems.push(format!(
+                        "{} has '{}', which is not a date",
+                        describe(element),
+                        value
+                    ));
+                }
+            }
+        }
+
+        if types.is_empty() {
+            continue;
+        }
+        let own = own_properties(element);
+        let has = |name: &str| own.iter().any(|property| property == name);
+        if types.contains(&"h-entry") {
+            if !has("u-url") {
+                problems.push(format!("{} has no u-url permalink", describe(element)));
+            }
+            if !has("p-name") && !has("e-content") {
+                problems.push(format!(
+                    "{} has neither a p-name nor an e-content",
+                    describe(element)
+                ));
+            }
+        }
+        if types.contains(&"h-card")
+            && !has("p-name")
+            && element.text().all(|text| text.trim().is_empty())
+        {
+            problems.push(format!("{} has no name", describe(element)));
+        }
+    }
+
+    for element in document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|element| {
+            element
+                .attr("rel")
+                .is_some_and(|rel| rel.split_whitespace().any(|rel| rel == "me"))
+        })
+    {
+        let absolute = element.attr("href").is_some_and(|href| {
+            ["https://", "http://", "mailto:"]
+                .iter()
+                .any(|scheme| href.starts_with(scheme))
+        });
+        if !absolute {
+            problems.push(format!(
+                "{} is rel=\"me\" but doesn't link to a full URL",
+                describe(element)
+            ));
+        }
+    }
+
+    problems
+}
+
+/// The microformats types (`h-*`) or properties (everything else) an element
+/// has.
+fn classes(element: ElementRef<'_>, types: bool) -> Vec<&str> {
+    element
+        .value()
+        .classes()
+        .filter(|class| CLASS_NAME.is_match(class) && class.starts_with("h-") == types)
+        .collect()
+}
+
+/// The microformat an element's properties belong to: its nearest ancestor
+/// with a type.
+fn root_of(element: ElementRef<'_>) -> Option<ElementRef<'_>> {
+    element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|ancestor| !classes(*ancestor, true).is_empty())
+}
+
+/// The properties of a microformat itself, and not of any nested in it.
+fn own_properties(root: ElementRef) -> Vec<String> {
+    let mut properties = Vec::new();
+    let mut pending = root
+        .children()
+        .filter_map(ElementRef::wrap)
+        .collect::<Vec<_>>();
+    while let Some(element) = pending.pop() {
+        properties.extend(classes(element, false).into_iter().map(String::from));
+        if classes(element, true).is_empty() {
+            pending.extend(element.children().filter_map(ElementRef::wrap));
+        }
+    }
+    properties
+}
+
+/// Where a `u-*` property's URL comes from, for the elements which must have
+/// one. Any other element's URL is its text.
+fn url_attribute(element: ElementRef) -> Option<&'static str> {
+    match element.value().name() {
+        "a" | "area" | "link" => Some("href"),
+        "img" | "audio" | "video" | "source" | "iframe" => Some("src"),
+        "object" => Some("data"),
+        _ => None,
+    }
+}
+
+/// Where a `dt-*` property's date comes from: the machine-readable
+/// attribute, if the element has one, or else its text.
+fn date_value(element: ElementRef) -> String {
+    let attribute = match element.value().name() {
+        "time" | "ins" | "del" => "datetime",
+        "abbr" => "title",
+        "data" | "input" => "value",
+        _ => "",
+    };
+    element
+        .attr(attribute)
+        .map(String::from)
+        .unwrap_or_else(|| element.text().collect::<String>())
+        .trim()
+        .to_string()
+}
+
+fn is_date(value: &str) -> bool {
+    DateTime::parse_from_rfc3339(value).is_ok()
+        || NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+        || NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").is_ok()
+        || NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").is_ok()
+}
+
+/// An element, as someone would find it in the source: `<a class="u-url">`.
+fn describe(element: ElementRef) -> String {
+    match element.attr("class") {
+        Some(class) => format!("<{} class=\"{}\">", element.value().name(), class),
+        None => format!("<{}>", element.value().name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_malformed_microformats() {
+        let html = r#"
+            <article class="h-entry">
+              <h1 class="p-name">Title</h1>
+              <time class="dt-published" datetime="yesterday">Yesterday</time>
+              <div class="p-author h-card"><a class="p-name u-url" href="/">Me</a></div>
+            </article>
+            <span class="p-category">orphan</span>
+            <a rel="me" href="/about">About</a>
+            <div class="h-4 p-4">Not microformats at all</div>
+        "#;
+        assert_eq!(
+            check(html),
+            vec![
+                String::from("<article class=\"h-entry\"> has no u-url permalink"),
+                String::from("<time class=\"dt-published\"> has 'yesterday', which is not a date"),
+                String::from("<span class=\"p-category\"> is not inside any microformat, so nothing will read it"),
+                String::from("<a> is rel=\"me\" but doesn't link to a full URL"),
+            ]
+        );
+    }
+}
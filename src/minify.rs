@@ -0,0 +1,403 @@
+//! Minify rendered HTML: drop comments, collapse runs of whitespace to single
+//! spaces (and drop them entirely between block-level tags), and optionally
+//! unquote attribute values which don't need quotes.
+//!
+//! Whitespace is significant in `<pre>`, `<code>`, and `<textarea>`, and
+//! `<script>` and `<style>` aren't HTML at all, so everything inside them,
+//! including the markup of highlighted code, is left exactly as it was.
+
+use crate::config::Minify;
+
+/// Elements whose contents are left alone.
+const PRESERVED: [&str; 5] = ["pre", "code", "textarea", "script", "style"];
+
+/// Elements whose contents are raw text, where `<` doesn't start a tag.
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+/// Elements around which whitespace never renders.
+const BLOCK: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "meta",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "source",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+/// A tag, as far as minifying is concerned.
+struct Tag<'a> {
+    /// The whole tag, from `<` to `>`.
+    source: &'a str,
+    /// The lowercased element name.
+    name: String,
+    closing: bool,
+}
+
+pub(crate) fn html(source: &str, config: &Minify) -> String {
+    let mut output = String::with_capacity(source.len());
+    // Whitespace waiting to find out whether it sits between two blocks.
+    let mut pending_space = false;
+    let mut after_block = true;
+    // How many preserved elements we're inside, by the name of the outermost.
+    let mut preserved: Option<(String, usize)> = None;
+
+    let mut rest = source;
+    while !rest.is_empty() {
+        if let Some((name, depth)) = &mut preserved {
+            // Copy everything up to the end of the preserved element as is,
+            // keeping track of any nested elements of the same kind.
+            let (tag_start, tag) = match next_tag(rest, RAW_TEXT.contains(&name.as_str()), name) {
+                Some(found) => found,
+                None => {
+                    output.push_str(rest);
+                    break;
+                }
+            };
+            output.push_str(&rest[..tag_start + tag.source.len()]);
+            rest = &rest[tag_start + tag.source.len()..];
+            if tag.name == *name {
+                if tag.closing {
+                    *depth -= 1;
+                } else if !is_void(&tag) {
+                    *depth += 1;
+                }
+            }
+            if *depth == 0 {
+                after_block = BLOCK.contains(&name.as_str());
+                preserved = None;
+            }
+            continue;
+        }
+
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+            // Conditional comments are instructions to old browsers.
+            if rest.starts_with("<!--[if") {
+                flush_space(&mut output, &mut pending_space);
+                output.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+
+        if rest.starts_with('<') {
+            let tag = match parse_tag(rest) {
+                Some(tag) => tag,
+                None => {
+                    // A stray `<`, which is just text.
+                    flush_space(&mut output, &mut pending_space);
+                    output.push('<');
+                    rest = &rest[1..];
+                    after_block = false;
+                    continue;
+                }
+            };
+
+            let is_block = BLOCK.contains(&tag.name.as_str());
+            if pending_space && !(is_block || after_block) {
+                output.push(' ');
+            }
+            pending_space = false;
+
+            if tag.name.is_empty() || tag.source.starts_with("<!") {
+                // A doctype or some such: no attributes to touch.
+                output.push_str(tag.source);
+            } else {
+                minify_tag(&mut output, &tag, config.unquote_attributes);
+            }
+            rest = &rest[tag.source.len()..];
+            after_block = is_block;
+
+            if !tag.closing && PRESERVED.contains(&tag.name.as_str()) && !is_void(&tag) {
+                preserved = Some((tag.name, 1));
+            }
+            continue;
+        }
+
+        // Text, up to the next tag.
+        let end = rest.find('<').unwrap_or(rest.len());
+        for (index, word) in rest[..end]
+            .split(|c: char| c.is_ascii_whitespace())
+            .enumerate()
+        {
+            if index > 0 {
+                pending_space = true;
+            }
+            if !word.is_empty() {
+                if after_block {
+                    // A leading space after a block never renders.
+                    pending_space = false;
+                }
+                flush_space(&mut output, &mut pending_space);
+                output.push_str(word);
+                after_block = false;
+            }
+        }
+        rest = &rest[end..];
+    }
+
+    output
+}
+
+fn flush_space(output: &mut String, pending_space: &mut bool) {
+    if *pending_space && !output.is_empty() {
+        output.push(' ');
+    }
+    *pending_space = false;
+}
+
+/// Find the next tag in `text`. In raw text, only the closing tag for `name`
+/// counts.
+fn next_tag<'a>(text: &'a str, raw: bool, name: &str) -> Option<(usize, Tag<'a>)> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find('<') {
+        let start = offset + start;
+        if let Some(tag) = parse_tag(&text[start..]) {
+            if !raw || (tag.closing && tag.name == name) {
+                return Some((start, tag));
+            }
+        }
+        offset = start + 1;
+    }
+    None
+}
+
+/// Parse the tag at the start of `text`, if there is one.
+fn parse_tag(text: &str) -> Option<Tag<'_>> {
+    let after = text.strip_prefix('<')?;
+    let (closing, after_slash) = match after.strip_prefix('/') {
+        Some(name) => (true, name),
+        None => (false, after),
+    };
+    let first = after_slash.chars().next()?;
+    if !(first.is_ascii_alphabetic() || first == '!' || first == '?') {
+        return None;
+    }
+
+    // Find the end of the tag, skipping over `>` in quoted attribute values.
+    let mut quote = None;
+    let mut end = None;
+    for (index, c) in text.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => {
+                end = Some(index);
+                break;
+            }
+            (None, _) => {}
+        }
+    }
+    let end = end?;
+
+    let name = after_slash
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    Some(Tag {
+        source: &text[..=end],
+        name,
+        closing,
+    })
+}
+
+fn is_void(tag: &Tag) -> bool {
+    tag.source.ends_with("/>")
+}
+
+/// Write `tag` with the whitespace between its attributes collapsed, and
+/// maybe without the quotes around their values.
+fn minify_tag(output: &mut String, tag: &Tag, unquote: bool) {
+    let source = tag.source;
+    let inner = &source[1..source.len() - 1];
+    let (inner, self_closing) = match inner.strip_suffix('/') {
+        Some(inner) => (inner, true),
+        None => (inner, false),
+    };
+
+    output.push('<');
+    let mut chars = inner.char_indices().peekable();
+    let mut first = true;
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_ascii_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        // The name, or the attribute's name.
+        let mut end = start;
+        while let Some(&(index, c)) = chars.peek() {
+            if c.is_ascii_whitespace() || c == '=' {
+                break;
+            }
+            end = index + c.len_utf8();
+            chars.next();
+        }
+        if !first {
+            output.push(' ');
+        }
+        first = false;
+        output.push_str(&inner[start..end]);
+
+        // Skip to an `=`, if there is one.
+        let mut lookahead = chars.clone();
+        while matches!(lookahead.peek(), Some((_, c)) if c.is_ascii_whitespace()) {
+            lookahead.next();
+        }
+        if !matches!(lookahead.peek(), Some((_, '='))) {
+            continue;
+        }
+        chars = lookahead;
+        chars.next();
+        while matches!(chars.peek(), Some((_, c)) if c.is_ascii_whitespace()) {
+            chars.next();
+        }
+
+        let value = match chars.peek() {
+            Some(&(value_start, quote)) if quote == '"' || quote == '\'' => {
+                chars.next();
+                let mut value_end = inner.len();
+                for (index, c) in chars.by_ref() {
+                    if c == quote {
+                        value_end = index;
+                        break;
+                    }
+                }
+                Some((quote, &inner[value_start + 1..value_end]))
+            }
+            Some(&(value_start, _)) => {
+                let mut value_end = inner.len();
+                while let Some(&(index, c)) = chars.peek() {
+                    if c.is_ascii_whitespace() {
+                        value_end = index;
+                        break;
+                    }
+                    chars.next();
+                }
+                Some(('"', &inner[value_start..value_end]))
+            }
+            None => None,
+        };
+
+        if let Some((quote, value)) = value {
+            output.push('=');
+            if unquote && can_unquote(value) {
+                output.push_str(value);
+            } else {
+                output.push(quote);
+                output.push_str(value);
+                output.push(quote);
+            }
+        }
+    }
+
+    if self_closing {
+        output.push_str(" /");
+    }
+    output.push('>');
+}
+
+/// Whether an attribute value means the same without quotes.
+fn can_unquote(value: &str) -> bool {
+    !value.is_empty()
+        && !value.ends_with('/')
+        && !value
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_and_comments() {
+        let config = Minify::default();
+        assert_eq!(
+            html(
+                "<article>\n  <!-- hi -->\n  <p>Some   <em>very</em>\n  nice text.</p>\n\n  <p>More.</p>\n</article>\n",
+                &config
+            ),
+            "<article><p>Some <em>very</em> nice text.</p><p>More.</p></article>"
+        );
+    }
+
+    #[test]
+    fn preserved_elements() {
+        let config = Minify::default();
+        let code = "<pre class=\"x\"><code>fn main() {\n    <span class=\"kw\">let</span>  x;\n}\n</code></pre>";
+        assert_eq!(
+            html(&format!("<p>Code:</p>\n{}\n", code), &config),
+            format!("<p>Code:</p>{}", code)
+        );
+    }
+
+    #[test]
+    fn pictures_are_inline() {
+        let config = Minify::default();
+        assert_eq!(
+            html(
+                "<p>See <picture>\n  <source srcset=\"a.webp\">\n  <img src=\"a.png\"></picture> here.</p>",
+                &config
+            ),
+            "<p>See <picture><source srcset=\"a.webp\"><img src=\"a.png\"></picture> here.</p>"
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let config = Minify {
+            unquote_attributes: true,
+            ..Minify::default()
+        };
+        assert_eq!(
+            html(
+                "<a  href=\"/a/b\" class=\"x y\"   title='it&#39;s'>link</a><img src=\"a.png\" alt=\"\" />",
+                &config
+            ),
+            "<a href=/a/b class=\"x y\" title=it&#39;s>link</a><img src=a.png alt=\"\" />"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 pub(crate) mod components;
 pub(crate) mod markdown;
 pub(crate) mod metadata;
+pub(crate) mod social;
 
 use std::{
     convert::TryFrom,
@@ -8,10 +9,20 @@ use std::{
 };
 
 use components::Components;
-use markdown::render_markdown;
+use markdown::headings::{table_of_contents, TocEntry};
+use markdown::links::InternalLink;
+use markdown::summary::{Summary, WORDS_PER_MINUTE};
+use markdown::{
+    citations::Citer, images::ImageProcessor, render_markdown, shortcodes::Shortcodes, Context,
+    Rendered,
+};
+use social::Social;
 use syntect::parsing::SyntaxSet;
 
+use crate::assets::Manifest;
 use crate::config::Config;
+use crate::graph::Backlink;
+use crate::history::Dates;
 
 use self::metadata::Metadata;
 
@@ -37,20 +48,142 @@ pub(crate) struct Page {
 
     /// The fully-rendered contents of the page.
     pub(crate) contents: String,
+
+    /// The contents of the page as plain text, for feeds and search.
+    pub(crate) text: String,
+
+    /// The page's table of contents, if its metadata asks for one.
+    pub(crate) toc: Option<Vec<TocEntry>>,
+
+    /// Where the page came from.
+    pub(crate) source: PathBuf,
+
+    /// The page's links to other pages. Until they are resolved, `contents`
+    /// has placeholders in their place.
+    pub(crate) links: Vec<InternalLink>,
+
+    /// The other pages linking to this one.
+    pub(crate) backlinks: Vec<Backlink>,
+
+    /// The page's own summary, if its header has one, or else its opening.
+    pub(crate) summary: Option<Summary>,
+
+    /// How many words of prose the page has.
+    pub(crate) words: usize,
+
+    /// How the page appears when shared.
+    pub(crate) social: Social,
 }
 
 impl Page {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         source: &Source,
-        root_dir: &PathBuf,
+        metadata: Metadata,
+        root_dir: &Path,
         syntax_set: &SyntaxSet,
+        config: &Config,
+        citer: &Citer,
+        shortcodes: &Shortcodes,
+        images: &ImageProcessor,
+        assets: &Manifest,
     ) -> Result<Self, String> {
-        let Components { header, body } = Components::try_from(source.contents.as_ref())?;
-        let metadata = Metadata::new(&source.path, root_dir, header)?;
+        let Components { body, .. } = Components::try_from(source.contents.as_ref())?;
+
+        let typography = config
+            .typography
+            .with_overrides(metadata.typography.as_ref());
+        let context = Context {
+            syntax_set,
+            source: &source.path,
+            root_dir,
+            typography: &typography,
+            citer,
+            shortcodes,
+            admonitions: &config.admonitions,
+            bibliography: metadata.bibliography.as_deref(),
+            math: &config.math,
+            images,
+            assets,
+            headings: &config.headings,
+            footnotes: &config.footnotes,
+            sidenotes: metadata.sidenotes.unwrap_or(config.footnotes.sidenotes),
+            first_line: source.contents[..source.contents.len() - body.len()]
+                .matches('\n')
+                .count()
+                + 1,
+        };
+        let Rendered {
+            html,
+            headings,
+            links,
+            text,
+            summary,
+            words,
+        } = render_markdown(body, &context)?;
+
+        let summary = match &metadata.summary {
+            Some(summary) => Some(Summary::from_markdown(summary)),
+            None => summary,
+        };
+
+        let image = metadata
+            .image
+            .as_deref()
+            .map(|image| {
+                if image.contains("://") {
+                    Ok(image.to_string())
+                } else {
+                    assets
+                        .resolve(image, &source.path)
+                        .map(|url| String::from(config.url.trim_end_matches('/')) + &url)
+                        .ok_or_else(|| format!("image '{}' is not an asset", image))
+                }
+            })
+            .transpose()?;
+        let social = Social::new(
+            &metadata,
+            summary.as_ref(),
+            url_for(&metadata.slug, config),
+            image,
+            config,
+        );
 
-        let contents = render_markdown(body, syntax_set)?;
+        let toc = if metadata.toc {
+            Some(table_of_contents(&headings))
+        } else {
+            None
+        };
 
-        Ok(Page { metadata, contents })
+        Ok(Page {
+            metadata,
+            contents: html,
+            text,
+            toc,
+            source: source.path.clone(),
+            links,
+            backlinks: Vec::new(),
+            summary,
+            words,
+            social,
+        })
+    }
+
+    /// A page's metadata, from its header alone, without rendering it: enough
+    /// to know where it will go, and whether to build it at all.
+    pub(crate) fn metadata_for(
+        source: &Source,
+        root_dir: &Path,
+        config: &Config,
+        history: Option<Dates>,
+    ) -> Result<Metadata, String> {
+        let Components { header, .. } = Components::try_from(source.contents.as_ref())?;
+        Metadata::new(&source.path, root_dir, header, &config.photos, history)
+    }
+
+    /// About how many minutes the page takes to read, rounding up.
+    pub(crate) fn reading_time(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE).max(1)
     }
 
     pub(crate) fn path(&self, output_dir: &Path) -> PathBuf {
@@ -59,10 +192,41 @@ impl Page {
 
     /// Given a config, generate the (canonicalized) URL for the page
     pub(crate) fn url(&self, config: &Config) -> String {
-        String::from(config.url.trim_end_matches('/')) + "/" + &self.metadata.slug
+        url_for(&self.metadata.slug, config)
     }
 }
 
+#[cfg(test)]
+impl Page {
+    /// An empty page at `path` in `/site/content`, with the given header.
+    pub(crate) fn for_tests(path: &str, header: &str, config: &Config) -> Page {
+        let source = Source {
+            path: PathBuf::from(format!("/site/content/{}", path)),
+            contents: format!("---\n{}\n---\n", header),
+        };
+        let metadata = Page::metadata_for(&source, Path::new("/site/content"), config, None)
+            .expect("test page header is legit");
+        let social = Social::new(&metadata, None, String::new(), None, config);
+        Page {
+            metadata,
+            contents: String::new(),
+            text: String::new(),
+            toc: None,
+            source: source.path,
+            links: Vec::new(),
+            backlinks: Vec::new(),
+            summary: None,
+            words: 0,
+            social,
+        }
+    }
+}
+
+/// The canonical URL for the page at `slug`.
+fn url_for(slug: &str, config: &Config) -> String {
+    String::from(config.url.trim_end_matches('/')) + "/" + slug
+}
+
 impl From<&Page> for lx_json_feed::FeedItem {
     fn from(_: &Page) -> Self {
         unimplemented!()
@@ -1,20 +1,119 @@
+mod admonitions;
+mod assets;
+mod blocks;
+pub(crate) mod citations;
+mod footnotes;
+pub(crate) mod headings;
+pub(crate) mod images;
+pub(crate) mod links;
+mod math;
+pub(crate) mod shortcodes;
+pub(crate) mod summary;
+pub(crate) mod text;
+mod typography;
+
+use std::path::Path;
+
 use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag};
 use syntect::html::{ClassStyle, ClassedHTMLGenerator};
 use syntect::parsing::SyntaxSet;
 
+use crate::assets::Manifest;
+use crate::config::{Admonitions, Footnotes, Headings, Math, Typography};
+
+use self::blocks::Blocks;
+use self::citations::Citer;
+use self::headings::Heading;
+use self::images::ImageProcessor;
+use self::links::InternalLink;
+use self::shortcodes::Shortcodes;
+use self::summary::Summary;
+use self::typography::Smartener;
+
 enum ParseState<'a> {
     NotInCodeBlock,
+    /// In a ```` ```math ```` block, accumulating its TeX.
+    Math(String),
     RequiresFirstLineParse,
     UnknownSyntax,
     KnownSyntax(ClassedHTMLGenerator<'a>),
 }
 
-pub(super) fn render_markdown(src: &str, syntax_set: &SyntaxSet) -> Result<String, String> {
-    let parser = Parser::new_ext(src, Options::all());
+/// Everything `render_markdown` needs besides the Markdown itself: some of it
+/// shared across the whole site, some of it specific to the page.
+pub(super) struct Context<'a> {
+    pub(super) syntax_set: &'a SyntaxSet,
+    /// The file being rendered, and the content directory, for resolving
+    /// links to other pages.
+    pub(super) source: &'a Path,
+    pub(super) root_dir: &'a Path,
+    pub(super) typography: &'a Typography,
+    pub(super) citer: &'a Citer,
+    pub(super) shortcodes: &'a Shortcodes,
+    pub(super) admonitions: &'a Admonitions,
+    /// The page's own bibliography, if it has one.
+    pub(super) bibliography: Option<&'a Path>,
+    pub(super) math: &'a Math,
+    pub(super) images: &'a ImageProcessor<'a>,
+    pub(super) assets: &'a Manifest,
+    pub(super) headings: &'a Headings,
+    pub(super) footnotes: &'a Footnotes,
+    /// Whether to render footnotes as sidenotes on this page.
+    pub(super) sidenotes: bool,
+    /// The line of the source file on which the Markdown starts, so that
+    /// errors can point at the right line.
+    pub(super) first_line: usize,
+}
+
+/// The output of rendering a page's Markdown.
+pub(super) struct Rendered {
+    pub(super) html: String,
+    /// Every heading in the document, in order.
+    pub(super) headings: Vec<Heading>,
+    /// Links to other pages, which still need their URLs filled in.
+    pub(super) links: Vec<InternalLink>,
+    /// The document as plain text.
+    pub(super) text: String,
+    /// The opening of the document, if it has any text.
+    pub(super) summary: Option<Summary>,
+    /// How many words of prose the document has, not counting code blocks.
+    pub(super) words: usize,
+}
+
+pub(super) fn render_markdown(src: &str, context: &Context) -> Result<Rendered, String> {
+    let syntax_set = context.syntax_set;
+
+    // We do our own, configurable, smart punctuation in the typography pass.
+    let mut options = Options::all();
+    options.remove(Options::ENABLE_SMART_PUNCTUATION);
+
+    let mut blocks = Blocks::default();
+    let src_with_shortcodes =
+        shortcodes::extract(src, context.shortcodes, context.first_line, &mut blocks)?;
+    let src_with_blocks = admonitions::extract(
+        &src_with_shortcodes,
+        context.admonitions,
+        context.first_line,
+        &mut blocks,
+    )?;
+    let extracted_math = math::extract(&src_with_blocks, context.math, context.first_line)?;
+    let parser = Parser::new_ext(&extracted_math.source, options);
+    let parsed = blocks.replace(merge_text(parser, &extracted_math.source));
+    let (parsed, bibliography) = citations::process(parsed, context.citer, context.bibliography)?;
+    let (parsed, links) = links::process(parsed, context.source, context.root_dir);
+
     let mut state = ParseState::NotInCodeBlock;
+    let mut smartener = Smartener::new(context.typography);
+    // Image alt text is rendered from the text events inside the image, so
+    // we must not introduce any markup there.
+    let mut image_depth = 0;
+    let mut in_heading = false;
+    let mut words = 0;
+    // How many ```` ```math ```` blocks we've rendered, to find the next.
+    let mut math_blocks = 0;
 
     let mut events = Vec::<Event>::with_capacity(src.len() * 2);
-    for event in parser {
+    for event in parsed {
         match event {
             Event::Text(text) => match &mut state {
                 // This is a little quirky: it hands off the text to the highlighter
@@ -31,7 +130,7 @@ pub(super) fn render_markdown(src: &str, syntax_set: &SyntaxSet) -> Result<Strin
                         Some(definition) => {
                             let mut generator = ClassedHTMLGenerator::new_with_class_style(
                                 definition,
-                                &syntax_set,
+                                syntax_set,
                                 ClassStyle::Spaced,
                             );
                             generator.parse_html_for_line_which_includes_newline(&text);
@@ -44,15 +143,75 @@ pub(super) fn render_markdown(src: &str, syntax_set: &SyntaxSet) -> Result<Strin
                         }
                     }
                 }
-                ParseState::UnknownSyntax | ParseState::NotInCodeBlock => {
-                    events.push(Event::Text(text))
+                ParseState::Math(ref mut tex) => tex.push_str(&text),
+                ParseState::UnknownSyntax => events.push(Event::Text(text)),
+                ParseState::NotInCodeBlock => {
+                    // An explicit `{#id}` is markup, not prose to smarten.
+                    let (text, explicit_id) = if in_heading {
+                        headings::split_explicit_id(&text)
+                    } else {
+                        (text.as_ref(), "")
+                    };
+                    for segment in math::segments(text) {
+                        match segment {
+                            math::Segment::Text(text) => {
+                                if image_depth == 0 {
+                                    words += summary::count_words(text);
+                                }
+                                events.extend(smartener.smarten(text, image_depth == 0))
+                            }
+                            // Alt text can't hold markup, so fall back to the TeX.
+                            math::Segment::Math(index) if image_depth > 0 => {
+                                let tex = extracted_math.tex[index].clone();
+                                events.push(Event::Text(tex.into()))
+                            }
+                            math::Segment::Math(index) => {
+                                let rendered = extracted_math.rendered[index].clone();
+                                smartener.saw(&extracted_math.tex[index]);
+                                events.push(Event::Html(rendered.into()))
+                            }
+                        }
+                    }
+                    if !explicit_id.is_empty() {
+                        events.push(Event::Text(explicit_id.to_string().into()));
+                    }
                 }
             },
+            Event::Code(ref code) => {
+                words += summary::count_words(code);
+                smartener.saw(code);
+                events.push(event);
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                smartener.saw(" ");
+                events.push(event);
+            }
+            Event::Start(Tag::Heading(..)) => {
+                in_heading = true;
+                smartener.reset();
+                events.push(event);
+            }
+            Event::End(Tag::Heading(..)) => {
+                in_heading = false;
+                smartener.reset();
+                events.push(event);
+            }
+            Event::Start(Tag::Image(..)) => {
+                image_depth += 1;
+                events.push(event);
+            }
+            Event::End(Tag::Image(..)) => {
+                image_depth -= 1;
+                events.push(event);
+            }
+            ref event if is_math_block(event) => {
+                state = ParseState::Math(String::new());
+            }
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(name))) => {
                 if let Some(looked_up) = syntax_set.find_syntax_by_token(name.as_ref()) {
                     state = ParseState::KnownSyntax(ClassedHTMLGenerator::new_with_class_style(
                         looked_up,
-                        &syntax_set,
+                        syntax_set,
                         ClassStyle::Spaced,
                     ));
                     let html = format!("<pre><code class='{}'>", looked_up.name);
@@ -72,6 +231,23 @@ pub(super) fn render_markdown(src: &str, syntax_set: &SyntaxSet) -> Result<Strin
                 }
             },
             Event::End(Tag::CodeBlock(_)) => match state {
+                ParseState::Math(tex) => {
+                    let rendered = math::render(&tex, true, context.math).map_err(|e| {
+                        // Only worth finding where the block was on failure.
+                        let line = Parser::new_ext(&extracted_math.source, options)
+                            .into_offset_iter()
+                            .filter(|(event, _)| is_math_block(event))
+                            .nth(math_blocks)
+                            .map(|(_, range)| extracted_math.line(range.start));
+                        match line {
+                            Some(line) => format!("line {}: could not render math: {}", line, e),
+                            None => format!("could not render math: {}", e),
+                        }
+                    })?;
+                    math_blocks += 1;
+                    state = ParseState::NotInCodeBlock;
+                    events.push(Event::Html(rendered.into()));
+                }
                 ParseState::KnownSyntax(generator) => {
                     let highlighted = generator.finalize();
                     state = ParseState::NotInCodeBlock;
@@ -85,13 +261,193 @@ pub(super) fn render_markdown(src: &str, syntax_set: &SyntaxSet) -> Result<Strin
                     unreachable!("Cannot *not* be in a code block when ending a coceblock")
                 }
             },
+            Event::Start(ref tag) | Event::End(ref tag) if is_block(tag) => {
+                smartener.reset();
+                events.push(event);
+            }
             _ => events.push(event),
         }
     }
 
+    let text = text::render(&events);
+    let events = images::process(events, context.images, context.source)?;
+    let events = assets::process(events, context.assets, context.source);
+    let summary = summary::extract(&events);
+    let mut events = footnotes::process(events, context.footnotes, context.sidenotes);
+    events.extend(bibliography.map(|html| Event::Html(html.into())));
+    let (events, headings) = headings::process(events, context.headings)?;
+
     let mut html_output = String::with_capacity(src.len() * 2);
 
     html::push_html(&mut html_output, events.into_iter());
 
-    Ok(html_output)
+    Ok(Rendered {
+        html: html_output,
+        headings,
+        links,
+        text,
+        summary,
+        words,
+    })
+}
+
+fn is_math_block(event: &Event) -> bool {
+    matches!(event, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(name))) if name.as_ref() == "math")
+}
+
+/// Block-level tags are boundaries for the typography pass: quotes never span
+/// them.
+fn is_block(tag: &Tag) -> bool {
+    !matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+/// The parser splits runs of text at (among other things) square brackets.
+/// Merge them back together, except in code blocks, where the highlighter
+/// needs the text line by line. Quotes escaped in `src` become stand-ins so
+/// the typography pass can still tell them apart once merged.
+fn merge_text<'a>(parser: Parser<'a>, src: &str) -> Vec<Event<'a>> {
+    let mut events = Vec::<Event>::new();
+    let mut in_code_block = false;
+    for (event, range) in parser.into_offset_iter() {
+        let event = match event {
+            Event::Text(text) if !in_code_block && is_escaped(src, range.start) => {
+                match text.chars().next().and_then(typography::escaped) {
+                    Some(stand_in) => Event::Text(format!("{}{}", stand_in, &text[1..]).into()),
+                    None => Event::Text(text),
+                }
+            }
+            event => event,
+        };
+
+        match event {
+            Event::Start(Tag::CodeBlock(..)) => {
+                in_code_block = true;
+                events.push(event);
+            }
+            Event::End(Tag::CodeBlock(..)) => {
+                in_code_block = false;
+                events.push(event);
+            }
+            Event::Text(text) if !in_code_block => match events.last_mut() {
+                Some(Event::Text(previous)) => {
+                    *previous = (previous.to_string() + &text).into();
+                }
+                _ => events.push(Event::Text(text)),
+            },
+            _ => events.push(event),
+        }
+    }
+
+    events
+}
+
+/// Whether the character at `offset` follows an odd number of backslashes,
+/// i.e. was escaped.
+fn is_escaped(src: &str, offset: usize) -> bool {
+    src[..offset]
+        .chars()
+        .rev()
+        .take_while(|&c| c == '\\')
+        .count()
+        % 2
+        == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use typography::{ESCAPED_DOUBLE_QUOTE, ESCAPED_SINGLE_QUOTE};
+
+    /// Render `markdown` as a page of a site with the default configuration.
+    fn render(markdown: &str) -> Result<Rendered, String> {
+        let config = Config::for_tests(Path::new("/site/nowhere/out"));
+        render_with(markdown, &Citer::new(&config.citations)?)
+    }
+
+    fn render_with(markdown: &str, citer: &Citer) -> Result<Rendered, String> {
+        let site = Path::new("/site/nowhere");
+        let config = Config::for_tests(&site.join("out"));
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let assets = Manifest::new(&config.assets, site)?;
+        let shortcodes = Shortcodes::load(&site.join("_ui/shortcodes"), &assets)?;
+        let images = ImageProcessor::new(&config.images, site, &config.output);
+        let context = Context {
+            syntax_set: &syntax_set,
+            source: &site.join("content/page.md"),
+            root_dir: &site.join("content"),
+            typography: &config.typography,
+            citer,
+            shortcodes: &shortcodes,
+            admonitions: &config.admonitions,
+            bibliography: None,
+            math: &config.math,
+            images: &images,
+            assets: &assets,
+            headings: &config.headings,
+            footnotes: &config.footnotes,
+            sidenotes: false,
+            first_line: 1,
+        };
+        render_markdown(markdown, &context)
+    }
+
+    #[test]
+    fn explicit_ids_are_not_smartened() {
+        let rendered = render(
+            "## Before -- after {#a--b}
+
+\"Hi\" -- \\\"there\\\"\n",
+        )
+        .expect("renders");
+        assert_eq!(rendered.headings[0].id, "a--b");
+        assert_eq!(rendered.headings[0].title, "Before – after");
+        assert!(
+            rendered.html.contains("<p>“Hi” – &quot;there&quot;</p>"),
+            "{}",
+            rendered.html
+        );
+    }
+
+    #[test]
+    fn bibliography_comes_after_the_endnotes() {
+        let citer = Citer::for_tests(
+            "apa",
+            "@book{barth1936, author = {Barth, Karl}, title = {Church Dogmatics}, year = {1936}}",
+        );
+        let rendered =
+            render_with("As @barth1936 argues.[^a]\n\n[^a]: A note.\n", &citer).expect("renders");
+        let endnotes = rendered.html.find("role=\"doc-endnotes\"");
+        let bibliography = rendered.html.find("role=\"doc-bibliography\"");
+        assert!(
+            endnotes.is_some() && endnotes < bibliography,
+            "{}",
+            rendered.html
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_get_stand_ins() {
+        let src = r#"He said \"hi\" and *it\'s* "fine" \\"x" `a\"b`"#;
+        let text = merge_text(Parser::new(src), src)
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            text,
+            vec![
+                format!("He said {0}hi{0} and ", ESCAPED_DOUBLE_QUOTE),
+                format!("it{}s", ESCAPED_SINGLE_QUOTE),
+                String::from(" \"fine\" \\\"x\" "),
+                String::from("a\\\"b"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,176 @@
+//! Admonitions: Markdown wrapped in an `<aside>`, written as
+//!
+//! ```text
+//! :::note An optional title
+//!
+//! Some *Markdown*.
+//!
+//! :::
+//! ```
+//!
+//! The kinds available (and their default titles and classes) come from the
+//! site's configuration. Admonitions may nest, each `:::` closing the innermost
+//! one still open; lines naming unknown kinds are left alone, along with the
+//! `:::` closing them.
+
+use std::borrow::Cow;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::escape::escape_html;
+use regex::Regex;
+
+use crate::config::Admonitions;
+
+use super::blocks::Blocks;
+use super::math;
+
+lazy_static! {
+    /// The start of an admonition, possibly inside a block quote.
+    static ref OPENING: Regex = Regex::new(
+        r"^(?P<prefix>[ ]{0,3}(?:>[ \t]*)*):{3,}[ \t]*(?P<kind>[A-Za-z][\w-]*)(?:[ \t]+(?P<title>.*?))?[ \t]*$"
+    )
+    .expect("admonition opening regex is legit");
+
+    /// The end of an admonition.
+    static ref CLOSING: Regex =
+        Regex::new(r"^(?P<prefix>[ ]{0,3}(?:>[ \t]*)*):{3,}[ \t]*$")
+            .expect("admonition closing regex is legit");
+}
+
+/// Find the admonitions in `src`, swapping their opening and closing lines for
+/// `blocks` placeholders. `first_line` is the line of the file on which `src`
+/// begins, so errors can point at the right place.
+pub(super) fn extract<'s>(
+    src: &'s str,
+    admonitions: &Admonitions,
+    first_line: usize,
+    blocks: &mut Blocks,
+) -> Result<Cow<'s, str>, String> {
+    if !src.contains(":::") {
+        return Ok(Cow::Borrowed(src));
+    }
+
+    let protected = math::protected_ranges(src);
+    let mut output = String::with_capacity(src.len());
+    // The kind and line of each admonition still waiting to be closed, or
+    // `None` for a block of a kind we don't know, whose closing is left alone.
+    let mut open: Vec<Option<(String, usize)>> = Vec::new();
+    let mut start = 0;
+    let mut replaced = false;
+
+    for (index, line) in src.split_inclusive('\n').enumerate() {
+        let line_number = first_line + index;
+        let line_start = start;
+        start += line.len();
+
+        let content = line.trim_end_matches(&['\n', '\r'][..]);
+        if protected
+            .iter()
+            .any(|&(start, end)| start <= line_start && line_start < end)
+        {
+            output.push_str(line);
+            continue;
+        }
+
+        let (prefix, html) = if let Some(captures) = CLOSING.captures(content) {
+            if !matches!(open.pop(), Some(Some(_))) {
+                output.push_str(line);
+                continue;
+            }
+            (captures.name("prefix"), String::from("</aside>\n"))
+        } else if let Some(captures) = OPENING.captures(content) {
+            let kind = &captures["kind"];
+            let admonition = match admonitions.get(kind) {
+                Some(admonition) => admonition,
+                None => {
+                    open.push(None);
+                    output.push_str(line);
+                    continue;
+                }
+            };
+            open.push(Some((kind.to_string(), line_number)));
+
+            let mut html = String::from("<aside class=\"");
+            escape_html(&mut html, admonition.class.as_deref().unwrap_or(kind))
+                .expect("writing to a String cannot fail");
+            html.push_str("\">\n");
+
+            let title = captures
+                .name("title")
+                .map(|title| title.as_str())
+                .or(admonition.title.as_deref());
+            if let Some(title) = title {
+                html.push_str("<p class=\"admonition-title\">");
+                escape_html(&mut html, title).expect("writing to a String cannot fail");
+                html.push_str("</p>\n");
+            }
+
+            (captures.name("prefix"), html)
+        } else {
+            output.push_str(line);
+            continue;
+        };
+
+        output.push_str(prefix.map_or("", |prefix| prefix.as_str()));
+        blocks.insert(&mut output, html);
+        if line.ends_with('\n') {
+            output.push('\n');
+        }
+        replaced = true;
+    }
+
+    if let Some((kind, line)) = open.into_iter().flatten().next_back() {
+        return Err(format!(
+            "line {}: ':::{}' is never closed with ':::'",
+            line, kind
+        ));
+    }
+
+    Ok(if replaced {
+        Cow::Owned(output)
+    } else {
+        Cow::Borrowed(src)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lines of `src` which became placeholders, by number.
+    fn replaced(src: &str) -> Result<Vec<usize>, String> {
+        let mut blocks = Blocks::default();
+        let output = extract(src, &Admonitions::default(), 1, &mut blocks)?;
+        Ok(src
+            .lines()
+            .zip(output.lines())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+            .map(|(index, _)| index + 1)
+            .collect())
+    }
+
+    #[test]
+    fn nesting() {
+        let src = ":::note\n\nOuter.\n\n:::warning Careful\n\nInner.\n\n:::\n\nOuter.\n\n:::\n";
+        assert_eq!(replaced(src), Ok(vec![1, 5, 9, 13]));
+    }
+
+    #[test]
+    fn unknown_kinds_are_left_alone() {
+        let src = ":::note\n\n:::details\n\nHidden.\n\n:::\n\nStill a note.\n\n:::\n";
+        assert_eq!(replaced(src), Ok(vec![1, 11]));
+    }
+
+    #[test]
+    fn unclosed() {
+        let src = "Text.\n\n:::note\n\n:::aside\n\n:::\n";
+        assert_eq!(
+            replaced(src),
+            Err(String::from("line 3: ':::note' is never closed with ':::'"))
+        );
+
+        // A stray closing line is just text.
+        assert_eq!(replaced("Text.\n\n:::\n"), Ok(vec![]));
+    }
+}
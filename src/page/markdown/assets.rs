@@ -0,0 +1,35 @@
+//! Point links and images to static assets at the assets' URLs, which differ
+//! from their paths when assets are fingerprinted.
+
+use std::path::Path;
+
+use pulldown_cmark::{Event, Tag};
+
+use crate::assets::Manifest;
+
+/// Rewrite every link or image whose destination is an asset. `source` is the
+/// path of the file being rendered, which relative paths are relative to.
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    manifest: &Manifest,
+    source: &Path,
+) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link(link_type, dest, title)) => {
+                let dest = manifest
+                    .resolve(&dest, source)
+                    .map_or(dest, |url| url.into());
+                Event::Start(Tag::Link(link_type, dest, title))
+            }
+            Event::Start(Tag::Image(link_type, dest, title)) => {
+                let dest = manifest
+                    .resolve(&dest, source)
+                    .map_or(dest, |url| url.into());
+                Event::Start(Tag::Image(link_type, dest, title))
+            }
+            event => event,
+        })
+        .collect()
+}
@@ -0,0 +1,128 @@
+//! Placeholders for chunks of HTML which wrap or stand between pieces of
+//! Markdown, like shortcodes and admonitions.
+//!
+//! Those are written as lines of their own in the source, and the Markdown
+//! around them has to be parsed along with the rest of the page, so they can't
+//! be rendered separately. Instead, each line is swapped for a placeholder
+//! before parsing, and each placeholder for its HTML afterward.
+
+use pulldown_cmark::{Event, Tag};
+
+/// Placeholders are the index of the HTML, wrapped in these private-use
+/// characters (distinct from those used for math).
+const OPEN: char = '\u{E002}';
+const CLOSE: char = '\u{E003}';
+
+/// The HTML for each placeholder in a page.
+#[derive(Default)]
+pub(super) struct Blocks {
+    html: Vec<String>,
+}
+
+impl Blocks {
+    /// Write a placeholder for `html` to `output`.
+    pub(super) fn insert(&mut self, output: &mut String, html: String) {
+        output.push(OPEN);
+        output.push_str(&self.html.len().to_string());
+        output.push(CLOSE);
+        self.html.push(html);
+    }
+
+    /// Swap the placeholders back out for their HTML. A placeholder on a line
+    /// of its own is a block: it ends any paragraph it would otherwise be part
+    /// of, and the paragraph resumes after it. Anywhere else, it goes inline.
+    pub(super) fn replace<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        if self.html.is_empty() {
+            return events;
+        }
+
+        let mut output = Vec::with_capacity(events.len());
+        let mut in_paragraph = false;
+        for event in events {
+            match event {
+                Event::Start(Tag::Paragraph) => {
+                    in_paragraph = true;
+                    output.push(event);
+                }
+                Event::End(Tag::Paragraph) => {
+                    in_paragraph = false;
+                    output.push(event);
+                }
+                Event::Text(ref text) if in_paragraph => match whole_placeholder(text) {
+                    Some(index) => {
+                        output.push(Event::End(Tag::Paragraph));
+                        output.push(Event::Html(self.html[index].clone().into()));
+                        output.push(Event::Start(Tag::Paragraph));
+                    }
+                    None => self.push_inline(&mut output, event),
+                },
+                event => self.push_inline(&mut output, event),
+            }
+        }
+
+        // Splitting paragraphs leaves empty ones, and line breaks at the edges
+        // of the ones which remain.
+        let mut cleaned: Vec<Event> = Vec::with_capacity(output.len());
+        for event in output {
+            match (cleaned.last(), &event) {
+                (Some(Event::Start(Tag::Paragraph)), Event::SoftBreak) => {}
+                (Some(Event::SoftBreak), Event::End(Tag::Paragraph)) => {
+                    cleaned.pop();
+                    cleaned.push(event);
+                }
+                _ => cleaned.push(event),
+            }
+            if let [.., Event::Start(Tag::Paragraph), Event::End(Tag::Paragraph)] =
+                cleaned.as_slice()
+            {
+                cleaned.truncate(cleaned.len() - 2);
+            }
+        }
+
+        cleaned
+    }
+
+    fn push_inline<'a>(&self, output: &mut Vec<Event<'a>>, event: Event<'a>) {
+        let text = match event {
+            Event::Text(text) if text.contains(OPEN) => text,
+            event => {
+                output.push(event);
+                return;
+            }
+        };
+
+        let mut rest = text.as_ref();
+        while let Some(start) = rest.find(OPEN) {
+            let end = match rest[start..].find(CLOSE) {
+                Some(len) => start + len,
+                None => break,
+            };
+
+            match rest[start + OPEN.len_utf8()..end].parse::<usize>() {
+                Ok(index) => {
+                    if start > 0 {
+                        output.push(Event::Text(rest[..start].to_string().into()));
+                    }
+                    output.push(Event::Html(self.html[index].clone().into()));
+                }
+                Err(_) => output.push(Event::Text(
+                    rest[..end + CLOSE.len_utf8()].to_string().into(),
+                )),
+            }
+
+            rest = &rest[end + CLOSE.len_utf8()..];
+        }
+
+        if !rest.is_empty() {
+            output.push(Event::Text(rest.to_string().into()));
+        }
+    }
+}
+
+fn whole_placeholder(text: &str) -> Option<usize> {
+    text.trim()
+        .strip_prefix(OPEN)?
+        .strip_suffix(CLOSE)?
+        .parse()
+        .ok()
+}
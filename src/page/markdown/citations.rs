@@ -0,0 +1,755 @@
+//! Pandoc-style citations, e.g. `[@key]`, `[see @key, p. 5; @other]`, or
+//! `[-@key]` to suppress the author's name, formatted with a CSL style from a
+//! BibTeX or CSL-JSON bibliography. A bare `@key` in running text, optionally
+//! followed by a locator (`@key [p. 5]`), cites the work as part of the
+//! sentence; since Twitter handles look just the same, it only counts if the
+//! key is in the bibliography.
+//!
+//! For note styles (e.g. Chicago notes-bibliography) every citation becomes a
+//! footnote, numbered along with any footnotes in the text, so that CSL can
+//! work out first and subsequent references, short forms, and *ibid.* (in
+//! styles which use it). For in-text styles, the citation is rendered where it
+//! appears. Either way, the works cited are collected into a bibliography at
+//! the end of the document, after any endnotes.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use hayagriva::archive::{self, ArchivedStyle};
+use hayagriva::citationberg::taxonomy::Locator;
+use hayagriva::citationberg::{
+    json as csl_json, FontStyle, FontVariant, FontWeight, IndependentStyle, Locale, LocaleCode,
+    Style, StyleClass, TextDecoration, VerticalAlign,
+};
+use hayagriva::{
+    BibliographyDriver, BibliographyRequest, CitationItem, CitationRequest, CitePurpose, ElemChild,
+    ElemChildren, Formatting, Library, LocatorPayload, SpecificLocator,
+};
+use lazy_static::lazy_static;
+use pulldown_cmark::{escape::escape_html, CowStr, Event, Tag};
+use regex::Regex;
+
+use crate::config::Citations;
+
+lazy_static! {
+    /// A bracketed group containing at least one `@key`.
+    static ref CITATION_GROUP: Regex =
+        Regex::new(r"\[(?P<body>(?:[^\[\]]*[\s;\-])?@[^\[\]]+)\]").expect("citation regex is legit");
+
+    /// An optional locator label (`p.`, `chap.`, `§`…) and its value, at the
+    /// start of the text following a citation key.
+    static ref LOCATOR: Regex = Regex::new(
+        r"^(?:(?P<label>[\p{L}§¶]+\.?)\s*)?(?P<value>[\dA-Za-z]*\d[\dA-Za-z]*(?:\s*[-–,]\s*[\dA-Za-z]*\d[\dA-Za-z]*)*|[ivxlcdm]+(?:\s*[-–,]\s*[ivxlcdm]+)*\b)(?P<suffix>.*)$"
+    )
+    .expect("locator regex is legit");
+
+    /// A bracketed locator right after a bare `@key`.
+    static ref BARE_LOCATOR: Regex =
+        Regex::new(r"^ ?\[(?P<locator>[^\[\]@]*)\]").expect("bare locator regex is legit");
+}
+
+/// Characters which may appear *inside* a citation key, though not at its end.
+const KEY_PUNCTUATION: &str = ":.#$%&-+?<>~/";
+
+/// Everything needed to format citations for the site, loaded once per build.
+pub(crate) struct Citer {
+    style: IndependentStyle,
+    locale: Option<LocaleCode>,
+    locales: Vec<Locale>,
+    bibliography: Option<Bibliography>,
+    bibliography_title: Option<String>,
+}
+
+impl Citer {
+    pub(crate) fn new(config: &Citations) -> Result<Citer, String> {
+        let style = load_style(&config.style)?;
+        let bibliography = config
+            .bibliography
+            .as_deref()
+            .map(Bibliography::from_file)
+            .transpose()?;
+
+        Ok(Citer {
+            style,
+            locale: config.locale.clone().map(LocaleCode),
+            locales: archive::locales(),
+            bibliography,
+            bibliography_title: config.bibliography_title.clone(),
+        })
+    }
+
+    fn is_note_style(&self) -> bool {
+        self.style.settings.class == StyleClass::Note
+    }
+}
+
+#[cfg(test)]
+impl Citer {
+    /// A citer for tests, with a bibliography given as BibTeX.
+    pub(super) fn for_tests(style: &str, bibtex: &str) -> Citer {
+        Citer {
+            style: load_style(style).expect("the style is bundled"),
+            locale: None,
+            locales: archive::locales(),
+            bibliography: Some(Bibliography::BibTeX(
+                hayagriva::io::from_biblatex_str(bibtex).expect("the BibTeX is legit"),
+            )),
+            bibliography_title: None,
+        }
+    }
+}
+
+/// A bibliography loaded from disk.
+pub(crate) enum Bibliography {
+    BibTeX(Library),
+    CslJson(HashMap<String, csl_json::Item>),
+}
+
+impl Bibliography {
+    pub(crate) fn from_file(path: &Path) -> Result<Bibliography, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read bibliography '{}': {}", path.display(), e))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bib") | Some("bibtex") => hayagriva::io::from_biblatex_str(&contents)
+                .map(Bibliography::BibTeX)
+                .map_err(|errors| {
+                    let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                    format!(
+                        "could not parse '{}':\n{}",
+                        path.display(),
+                        errors.join("\n")
+                    )
+                }),
+            Some("json") => serde_json::from_str::<Vec<csl_json::Item>>(&contents)
+                .map_err(|e| format!("could not parse '{}':\n{}", path.display(), e))?
+                .into_iter()
+                .map(|item| match item.id() {
+                    Some(id) => Ok((id.into_owned(), item)),
+                    None => Err(format!("item without an `id` in '{}'", path.display())),
+                })
+                .collect::<Result<_, _>>()
+                .map(Bibliography::CslJson),
+            _ => Err(format!(
+                "unknown bibliography format for '{}' (expected `.bib` or `.json`)",
+                path.display()
+            )),
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        match self {
+            Bibliography::BibTeX(library) => library.get(key).is_some(),
+            Bibliography::CslJson(items) => items.contains_key(key),
+        }
+    }
+}
+
+fn load_style(style: &str) -> Result<IndependentStyle, String> {
+    let style = if style.ends_with(".csl") {
+        let xml = std::fs::read_to_string(style)
+            .map_err(|e| format!("could not read CSL style '{}': {}", style, e))?;
+        Style::from_xml(&xml).map_err(|e| format!("could not parse '{}': {}", style, e))?
+    } else {
+        ArchivedStyle::by_name(style)
+            .ok_or_else(|| format!("unknown CSL style '{}'", style))?
+            .get()
+    };
+
+    match style {
+        Style::Independent(style) => Ok(style),
+        Style::Dependent(dependent) => {
+            match ArchivedStyle::by_id(&dependent.parent_link.href).map(ArchivedStyle::get) {
+                Some(Style::Independent(parent)) => Ok(parent),
+                _ => Err(format!(
+                    "the parent style '{}' is not available",
+                    dependent.parent_link.href
+                )),
+            }
+        }
+    }
+}
+
+/// One work cited within a citation.
+struct CiteItem {
+    key: String,
+    prefix: String,
+    locator: Option<(Locator, String)>,
+    suffix: String,
+    suppress_author: bool,
+}
+
+/// A single bracketed citation, which may cite several works.
+struct Citation {
+    items: Vec<CiteItem>,
+    /// Whether the citation is part of the sentence (a bare `@key`).
+    author_in_text: bool,
+    /// The footnote this citation will occupy, for note styles.
+    note_number: usize,
+}
+
+/// Run the citations through CSL. This is a macro rather than a function
+/// because `hayagriva` does not export the trait its entry types share.
+macro_rules! drive {
+    ($citer:expr, $citations:expr, $lookup:expr) => {{
+        let lookup = $lookup;
+        let mut driver = BibliographyDriver::new();
+        for citation in $citations {
+            let items = citation
+                .items
+                .iter()
+                .map(|item| {
+                    let entry = lookup(item.key.as_str()).expect("keys were checked while parsing");
+                    let locator = item.locator.as_ref().map(|(locator, value)| {
+                        SpecificLocator(*locator, LocatorPayload::Str(value.as_str()))
+                    });
+                    let purpose = if item.suppress_author {
+                        Some(CitePurpose::Year)
+                    } else if citation.author_in_text && !$citer.is_note_style() {
+                        Some(CitePurpose::Prose)
+                    } else {
+                        None
+                    };
+                    CitationItem::new(entry, locator, None, false, purpose)
+                })
+                .collect();
+
+            driver.citation(CitationRequest::new(
+                items,
+                &$citer.style,
+                $citer.locale.clone(),
+                &$citer.locales,
+                Some(citation.note_number),
+            ));
+        }
+
+        driver.finish(BibliographyRequest::new(
+            &$citer.style,
+            $citer.locale.clone(),
+            &$citer.locales,
+        ))
+    }};
+}
+
+/// Find and format every citation in the document, replacing each with either
+/// a footnote reference or the inline citation and appending footnotes as
+/// appropriate. The bibliography, if any, is returned separately, since it
+/// belongs after the endnotes.
+///
+/// This expects adjacent text events to have been merged, since the parser
+/// splits text at square brackets.
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    citer: &Citer,
+    page_bibliography: Option<&Path>,
+) -> Result<(Vec<Event<'a>>, Option<String>), String> {
+    let page_bibliography = page_bibliography.map(Bibliography::from_file).transpose()?;
+    let bibliography = match page_bibliography.as_ref().or(citer.bibliography.as_ref()) {
+        Some(bibliography) => bibliography,
+        None => return Ok((events, None)),
+    };
+
+    // Find the citations, keeping track of how many notes precede each.
+    let mut pieces = Vec::with_capacity(events.len());
+    let mut citations = Vec::new();
+    let mut seen_notes = HashSet::new();
+    let mut in_code_block = false;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(..)) => {
+                in_code_block = true;
+                pieces.push(Piece::Event(event));
+            }
+            Event::End(Tag::CodeBlock(..)) => {
+                in_code_block = false;
+                pieces.push(Piece::Event(event));
+            }
+            Event::FootnoteReference(ref label) => {
+                seen_notes.insert(label.to_string());
+                pieces.push(Piece::Event(event));
+            }
+            Event::Text(text) if !in_code_block => {
+                let mut last_end = 0;
+                for found in find_citations(&text, bibliography)? {
+                    if found.start > last_end {
+                        let before = text[last_end..found.start].to_string();
+                        pieces.push(Piece::Event(Event::Text(before.into())));
+                    }
+
+                    pieces.push(Piece::Citation(citations.len()));
+                    citations.push(Citation {
+                        items: found.items,
+                        author_in_text: found.author_in_text,
+                        note_number: seen_notes.len() + citations.len() + 1,
+                    });
+                    last_end = found.end;
+                }
+
+                if last_end == 0 {
+                    pieces.push(Piece::Event(Event::Text(text)));
+                } else if last_end < text.len() {
+                    let rest = text[last_end..].to_string();
+                    pieces.push(Piece::Event(Event::Text(rest.into())));
+                }
+            }
+            _ => pieces.push(Piece::Event(event)),
+        }
+    }
+
+    if citations.is_empty() {
+        let events = pieces
+            .into_iter()
+            .filter_map(|piece| match piece {
+                Piece::Event(event) => Some(event),
+                Piece::Citation(_) => None,
+            })
+            .collect();
+        return Ok((events, None));
+    }
+
+    let rendered = match bibliography {
+        Bibliography::BibTeX(library) => drive!(citer, &citations, |key| library.get(key)),
+        Bibliography::CslJson(items) => drive!(citer, &citations, |key| items.get(key)),
+    };
+
+    let rendered_citations = rendered
+        .citations
+        .iter()
+        .zip(&citations)
+        .map(|(rendered, citation)| {
+            let first = citation.items.first().expect("citations are never empty");
+            let last = citation.items.last().expect("citations are never empty");
+            let mut html = String::new();
+            escape_html(&mut html, &first.prefix).expect("writing to a String cannot fail");
+            write_html(&mut html, &rendered.citation);
+            escape_html(&mut html, &last.suffix).expect("writing to a String cannot fail");
+            html
+        })
+        .collect::<Vec<_>>();
+
+    let note_style = citer.is_note_style();
+    let mut events = Vec::with_capacity(pieces.len() + citations.len() * 5);
+    for piece in pieces {
+        match piece {
+            Piece::Event(event) => events.push(event),
+            Piece::Citation(index) if note_style => {
+                events.push(Event::FootnoteReference(note_label(index).into()))
+            }
+            Piece::Citation(index) => events.push(Event::Html(
+                format!("<cite>{}</cite>", rendered_citations[index]).into(),
+            )),
+        }
+    }
+
+    if note_style {
+        for (index, html) in rendered_citations.into_iter().enumerate() {
+            let label = CowStr::from(note_label(index));
+            events.push(Event::Start(Tag::FootnoteDefinition(label.clone())));
+            events.push(Event::Start(Tag::Paragraph));
+            events.push(Event::Html(html.into()));
+            events.push(Event::End(Tag::Paragraph));
+            events.push(Event::End(Tag::FootnoteDefinition(label)));
+        }
+    }
+
+    let bibliography = rendered.bibliography.map(|rendered_bibliography| {
+        let mut html = String::from("<section class=\"bibliography\" role=\"doc-bibliography\">");
+        if let Some(title) = &citer.bibliography_title {
+            html.push_str("<h2>");
+            escape_html(&mut html, title).expect("writing to a String cannot fail");
+            html.push_str("</h2>");
+        }
+        for item in rendered_bibliography.items {
+            html.push_str("<div class=\"csl-entry\" id=\"ref-");
+            escape_html(&mut html, &item.key).expect("writing to a String cannot fail");
+            html.push_str("\">");
+            write_html(&mut html, &item.content);
+            html.push_str("</div>");
+        }
+        html.push_str("</section>");
+        html
+    });
+
+    Ok((events, bibliography))
+}
+
+enum Piece<'a> {
+    Event(Event<'a>),
+    /// The index of a citation in the document.
+    Citation(usize),
+}
+
+/// A citation found in some text, and where.
+struct Found {
+    start: usize,
+    end: usize,
+    items: Vec<CiteItem>,
+    author_in_text: bool,
+}
+
+/// Find the citations in some text, in order: bracketed citations, which
+/// must all be in the bibliography, and bare keys, which are only citations
+/// if they are.
+fn find_citations(text: &str, bibliography: &Bibliography) -> Result<Vec<Found>, String> {
+    let mut found = Vec::new();
+    let mut last_end = 0;
+    for group in CITATION_GROUP.captures_iter(text) {
+        let whole = group.get(0).expect("capture 0 is always present");
+        let items = match parse_citation(&group["body"]) {
+            Some(items) => items,
+            None => continue,
+        };
+        for item in &items {
+            if !bibliography.contains(&item.key) {
+                return Err(format!("no bibliography entry for '@{}'", item.key));
+            }
+        }
+
+        found.extend(bare_citations(text, last_end, whole.start(), bibliography));
+        found.push(Found {
+            start: whole.start(),
+            end: whole.end(),
+            items,
+            author_in_text: false,
+        });
+        last_end = whole.end();
+    }
+    found.extend(bare_citations(text, last_end, text.len(), bibliography));
+    Ok(found)
+}
+
+/// The bare `@key` citations in `text[start..end]`.
+fn bare_citations(text: &str, start: usize, end: usize, bibliography: &Bibliography) -> Vec<Found> {
+    let mut found = Vec::new();
+    let mut search_from = start;
+    while let Some(offset) = text[search_from..end].find('@') {
+        let at = search_from + offset;
+        search_from = at + 1;
+
+        // A key can't follow anything which could be part of a word, or an
+        // email address would be a citation.
+        let after_word = text[..at]
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || KEY_PUNCTUATION.contains(c));
+        if after_word {
+            continue;
+        }
+
+        let after_at = &text[at + 1..end];
+        let key_len = after_at
+            .char_indices()
+            .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || KEY_PUNCTUATION.contains(c)))
+            .map_or(after_at.len(), |(index, _)| index);
+        let key = after_at[..key_len].trim_end_matches(|c| KEY_PUNCTUATION.contains(c));
+        if key.is_empty() || !bibliography.contains(key) {
+            continue;
+        }
+
+        let mut citation_end = at + 1 + key.len();
+        let item = match BARE_LOCATOR.captures(&text[citation_end..end]) {
+            Some(captures) => {
+                citation_end += captures[0].len();
+                parse_item(&format!("@{}, {}", key, &captures["locator"]))
+            }
+            None => parse_item(&format!("@{}", key)),
+        };
+        if let Some(item) = item {
+            found.push(Found {
+                start: at,
+                end: citation_end,
+                items: vec![item],
+                author_in_text: true,
+            });
+            search_from = citation_end;
+        }
+    }
+    found
+}
+
+fn note_label(index: usize) -> String {
+    format!("cite-{}", index + 1)
+}
+
+/// Parse the body of a bracketed citation, e.g. `see @doe99, pp. 33-35; @smith`.
+/// Returns `None` if any part of it is not a citation, since then the brackets
+/// were just brackets.
+fn parse_citation(body: &str) -> Option<Vec<CiteItem>> {
+    body.split(';').map(parse_item).collect()
+}
+
+fn parse_item(item: &str) -> Option<CiteItem> {
+    let at = item.char_indices().find_map(|(index, c)| {
+        let starts_key = c == '@'
+            && item[..index]
+                .chars()
+                .last()
+                .is_none_or(|before| before.is_whitespace() || before == '-');
+        if starts_key {
+            Some(index)
+        } else {
+            None
+        }
+    })?;
+
+    let (prefix, suppress_author) = match item[..at].strip_suffix('-') {
+        Some(prefix) => (prefix, true),
+        None => (&item[..at], false),
+    };
+
+    let after_at = &item[at + 1..];
+    let key_len = after_at
+        .char_indices()
+        .find(|&(_, c)| !(c.is_alphanumeric() || c == '_' || KEY_PUNCTUATION.contains(c)))
+        .map_or(after_at.len(), |(index, _)| index);
+    let key = after_at[..key_len].trim_end_matches(|c| KEY_PUNCTUATION.contains(c));
+    if key.is_empty() {
+        return None;
+    }
+
+    let rest = after_at[key.len()..].trim_start_matches(',').trim();
+    let (locator, suffix) = match LOCATOR.captures(rest) {
+        Some(captures) => {
+            let label = captures.name("label").map(|label| label.as_str());
+            let value = &captures["value"];
+            // Without a label, only a number reads as a (page) locator.
+            let locator = match label {
+                Some(label) => locator_for(label),
+                None if value.chars().any(|c| c.is_ascii_digit()) => Some(Locator::Page),
+                None => None,
+            };
+            match locator {
+                Some(locator) => (
+                    Some((locator, value.to_string())),
+                    captures["suffix"].to_string(),
+                ),
+                None => (None, rest.to_string()),
+            }
+        }
+        None => (None, rest.to_string()),
+    };
+
+    Some(CiteItem {
+        key: key.to_string(),
+        prefix: prefix.trim_start().to_string(),
+        locator,
+        suffix: if suffix.is_empty() || suffix.starts_with(',') {
+            suffix
+        } else {
+            format!(" {}", suffix.trim_start())
+        },
+        suppress_author,
+    })
+}
+
+/// Map the locator labels Pandoc understands onto CSL locators.
+fn locator_for(label: &str) -> Option<Locator> {
+    let locator = match label.trim_end_matches('.') {
+        "p" | "pp" | "page" | "pages" => Locator::Page,
+        "chap" | "chaps" | "chapter" | "chapters" => Locator::Chapter,
+        "sec" | "secs" | "section" | "sections" | "§" | "§§" => Locator::Section,
+        "vol" | "vols" | "volume" | "volumes" => Locator::Volume,
+        "n" | "nn" | "note" | "notes" => Locator::Note,
+        "l" | "ll" | "line" | "lines" => Locator::Line,
+        "para" | "paras" | "paragraph" | "paragraphs" | "¶" | "¶¶" => Locator::Paragraph,
+        "fig" | "figs" | "figure" | "figures" => Locator::Figure,
+        "col" | "cols" | "column" | "columns" => Locator::Column,
+        "bk" | "bks" | "book" | "books" => Locator::Book,
+        "pt" | "pts" | "part" | "parts" => Locator::Part,
+        "v" | "vv" | "verse" | "verses" => Locator::Verse,
+        "no" | "nos" | "number" | "numbers" | "issue" => Locator::Issue,
+        "op" | "opus" => Locator::Opus,
+        "sv" | "s.v" => Locator::SubVerbo,
+        _ => return None,
+    };
+
+    Some(locator)
+}
+
+/// Write CSL output as (semantic, escaped) HTML.
+fn write_html(html: &mut String, children: &ElemChildren) {
+    for child in &children.0 {
+        match child {
+            ElemChild::Text(text) => write_formatted(html, &text.text, &text.formatting),
+            ElemChild::Elem(elem) => write_html(html, &elem.children),
+            ElemChild::Markup(markup) => html.push_str(markup),
+            ElemChild::Link { text, url } => {
+                html.push_str("<a href=\"");
+                escape_html(&mut *html, url).expect("writing to a String cannot fail");
+                html.push_str("\">");
+                write_formatted(html, &text.text, &text.formatting);
+                html.push_str("</a>");
+            }
+            ElemChild::Transparent { .. } => {}
+        }
+    }
+}
+
+fn write_formatted(html: &mut String, text: &str, formatting: &Formatting) {
+    let mut closers = Vec::new();
+    let mut open = |html: &mut String, open: &str, close: &'static str| {
+        html.push_str(open);
+        closers.push(close);
+    };
+
+    if formatting.font_style == FontStyle::Italic {
+        open(html, "<i>", "</i>");
+    }
+    if formatting.font_weight == FontWeight::Bold {
+        open(html, "<b>", "</b>");
+    }
+    if formatting.font_variant == FontVariant::SmallCaps {
+        open(html, "<span class=\"small-caps\">", "</span>");
+    }
+    if formatting.text_decoration == TextDecoration::Underline {
+        open(html, "<u>", "</u>");
+    }
+    match formatting.vertical_align {
+        VerticalAlign::Sup => open(html, "<sup>", "</sup>"),
+        VerticalAlign::Sub => open(html, "<sub>", "</sub>"),
+        _ => {}
+    }
+
+    escape_html(&mut *html, text).expect("writing to a String cannot fail");
+
+    for close in closers.into_iter().rev() {
+        html.push_str(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::{html, Options, Parser};
+
+    const BIBTEX: &str = "
+        @book{barth1936, author = {Barth, Karl}, title = {Church Dogmatics}, year = {1936}}
+        @book{smith2001, author = {Smith, Jane}, title = {On Things}, year = {2001}}
+    ";
+
+    fn render(markdown: &str, style: &str) -> Result<String, String> {
+        let citer = Citer::for_tests(style, BIBTEX);
+        let events = super::super::merge_text(Parser::new_ext(markdown, Options::all()), markdown);
+        let (events, bibliography) = process(events, &citer, None)?;
+        let mut rendered = String::new();
+        html::push_html(&mut rendered, events.into_iter());
+        rendered.push_str(&bibliography.unwrap_or_default());
+        Ok(rendered)
+    }
+
+    #[test]
+    fn prefixes_locators_and_suppressed_authors() {
+        let items = parse_citation("see @a, p. 5; -@b").expect("it is a citation");
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].key, "a");
+        assert_eq!(items[0].prefix, "see ");
+        assert_eq!(items[0].locator, Some((Locator::Page, String::from("5"))));
+        assert_eq!(items[0].suffix, "");
+        assert!(!items[0].suppress_author);
+
+        assert_eq!(items[1].key, "b");
+        assert_eq!(items[1].prefix, "");
+        assert_eq!(items[1].locator, None);
+        assert!(items[1].suppress_author);
+    }
+
+    #[test]
+    fn bare_keys() {
+        assert_eq!(
+            render("As @barth1936 [p. 5] argues.", "apa").expect("the key exists"),
+            "<p>As <cite>Barth (1936, p. 5)</cite> argues.</p>\n\
+             <section class=\"bibliography\" role=\"doc-bibliography\">\
+             <div class=\"csl-entry\" id=\"ref-barth1936\">Barth, K. (1936). <i>Church Dogmatics</i>.</div>\
+             </section>"
+        );
+    }
+
+    #[test]
+    fn unknown_keys() {
+        assert_eq!(
+            render("A claim [@nobody].", "chicago-notes"),
+            Err(String::from("no bibliography entry for '@nobody'"))
+        );
+    }
+
+    #[test]
+    fn not_citations() {
+        let markdown = "Write to me@example.com [or here], or @someone.";
+        assert_eq!(
+            render(markdown, "chicago-notes").expect("there are no citations"),
+            "<p>Write to me@example.com [or here], or @someone.</p>\n"
+        );
+    }
+
+    #[test]
+    fn notes() {
+        let rendered = render(
+            "One [@barth1936]. Two [@smith2001; @barth1936]. Three [@barth1936, p. 5].",
+            "chicago-notes",
+        )
+        .expect("the keys exist");
+        assert!(rendered.starts_with(
+            "<p>One <sup class=\"footnote-reference\"><a href=\"#cite-1\">1</a></sup>. \
+             Two <sup class=\"footnote-reference\"><a href=\"#cite-2\">2</a></sup>. \
+             Three <sup class=\"footnote-reference\"><a href=\"#cite-3\">3</a></sup>.</p>"
+        ));
+        assert_eq!(rendered.matches("class=\"footnote-definition\"").count(), 3);
+        // Later citations of the same work get the short form.
+        assert!(rendered.contains("<p>Karl Barth, <i>Church Dogmatics</i> (1936).</p>"));
+        assert!(rendered.contains(
+            "<p>Jane Smith, <i>On Things</i> (2001); Barth, <i>Church Dogmatics</i>.</p>"
+        ));
+        assert!(rendered.contains("<p>Barth, <i>Church Dogmatics</i>, 5.</p>"));
+    }
+
+    #[test]
+    fn ibid() {
+        // The bundled note styles all prefer short forms to *ibid.*, so use
+        // one which doesn't.
+        let style = std::env::temp_dir().join(format!("lx-ibid-{}.csl", std::process::id()));
+        std::fs::write(
+            &style,
+            r#"<?xml version="1.0" encoding="utf-8"?>
+            <style xmlns="http://purl.org/net/xbiblio/csl" class="note" version="1.0">
+              <info><title>Ibid.</title><id>https://example.com/ibid</id></info>
+              <citation>
+                <layout suffix=".">
+                  <choose>
+                    <if position="ibid-with-locator">
+                      <group delimiter=", "><text term="ibid" text-case="capitalize-first"/><group delimiter=" "><label variable="locator" form="short"/><text variable="locator"/></group></group>
+                    </if>
+                    <else-if position="ibid"><text term="ibid" text-case="capitalize-first"/></else-if>
+                    <else><group delimiter=", "><names variable="author"/><text variable="title" font-style="italic"/><text variable="locator"/></group></else>
+                  </choose>
+                </layout>
+              </citation>
+            </style>"#,
+        )
+        .expect("can write the style");
+
+        let rendered = render(
+            "One [@barth1936, p. 5]. Two [@barth1936, p. 7]. Three [@barth1936, p. 7]. \
+             Four [@smith2001]. Five [@barth1936, p. 7].",
+            &style.to_string_lossy(),
+        )
+        .expect("the keys exist");
+        std::fs::remove_file(&style).ok();
+
+        let notes = rendered
+            .lines()
+            .filter(|line| line.starts_with("<p>") && !line.starts_with("<p>One"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            vec![
+                "<p>Karl Barth, <i>Church Dogmatics</i>, 5.</p>",
+                "<p>Ibid., p. 7.</p>",
+                "<p>Ibid.</p>",
+                "<p>Jane Smith, <i>On Things</i>.</p>",
+                "<p>Karl Barth, <i>Church Dogmatics</i>, 7.</p>",
+            ]
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Footnotes, rendered either as endnotes collected at the end of the document
+//! with links back to every reference, or as Tufte-style sidenotes.
+//!
+//! pulldown-cmark's own rendering leaves each definition wherever it appears in
+//! the source and gives no way back to the text, so we take over entirely:
+//! definitions are pulled out of the event stream, numbered in order of first
+//! reference, and emitted where we want them.
+
+use std::collections::HashMap;
+
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::config::Footnotes;
+
+/// A footnote's definition, and how it has been referenced so far.
+struct Note<'a> {
+    number: usize,
+    contents: Vec<Event<'a>>,
+    references: usize,
+}
+
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    config: &Footnotes,
+    sidenotes: bool,
+) -> Vec<Event<'a>> {
+    let (events, definitions) = take_definitions(events);
+    if definitions.is_empty() {
+        return events;
+    }
+
+    let mut resolver = Resolver {
+        definitions,
+        notes: HashMap::new(),
+        order: Vec::new(),
+        sidenotes,
+    };
+    let mut output = resolver.resolve(events);
+    let Resolver {
+        mut notes, order, ..
+    } = resolver;
+
+    if sidenotes {
+        return output;
+    }
+
+    output.push(Event::Html(
+        "<section class=\"footnotes\" role=\"doc-endnotes\">\n<hr>\n<ol>\n".into(),
+    ));
+    for label in order {
+        let note = notes
+            .remove(&label)
+            .expect("every label in the order has a note");
+        output.push(Event::Html(
+            format!("<li id=\"fn-{}\">\n", note.number).into(),
+        ));
+
+        let back_references = (1..=note.references)
+            .map(|reference| {
+                let marker = if note.references > 1 {
+                    format!("<sup>{}</sup>", reference)
+                } else {
+                    String::new()
+                };
+                format!(
+                    " <a href=\"#{}\" class=\"footnote-back\" role=\"doc-backlink\">{}{}</a>",
+                    reference_id(note.number, reference),
+                    config.back_reference,
+                    marker
+                )
+            })
+            .collect::<String>();
+
+        // Put the back-references at the end of the last paragraph if there is
+        // one, so they don't dangle on a line of their own.
+        let mut contents = note.contents;
+        match contents.last() {
+            Some(Event::End(Tag::Paragraph)) => {
+                let end = contents.pop().expect("just checked");
+                contents.push(Event::Html(back_references.into()));
+                contents.push(end);
+            }
+            _ => contents.push(Event::Html(back_references.into())),
+        }
+
+        output.extend(contents);
+        output.push(Event::Html("</li>\n".into()));
+    }
+    output.push(Event::Html("</ol>\n</section>\n".into()));
+
+    output
+}
+
+/// Replaces footnote references with their markup, numbering notes in order
+/// of first reference. Definitions can reference other notes (including the
+/// notes citations become), so their contents are resolved the same way, as
+/// soon as they're first referenced.
+struct Resolver<'a> {
+    definitions: HashMap<String, Vec<Event<'a>>>,
+    notes: HashMap<String, Note<'a>>,
+    order: Vec<String>,
+    sidenotes: bool,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        let mut output = Vec::with_capacity(events.len());
+        for event in events {
+            match event {
+                Event::FootnoteReference(label) => self.reference(&label, &mut output),
+                event => output.push(event),
+            }
+        }
+        output
+    }
+
+    fn reference(&mut self, label: &str, output: &mut Vec<Event<'a>>) {
+        let first = match self.notes.get_mut(label) {
+            Some(note) => {
+                note.references += 1;
+                false
+            }
+            None => {
+                let contents = match self.definitions.remove(label) {
+                    Some(contents) => contents,
+                    // Like Pandoc, treat references to missing notes as text.
+                    None => {
+                        output.push(Event::Text(format!("[^{}]", label).into()));
+                        return;
+                    }
+                };
+                self.order.push(label.to_string());
+                self.notes.insert(
+                    label.to_string(),
+                    Note {
+                        number: self.order.len(),
+                        contents: Vec::new(),
+                        references: 1,
+                    },
+                );
+                // A note which (eventually) refers back to itself just gets
+                // a reference to itself, since it's already numbered.
+                let contents = self.resolve(contents);
+                self.notes
+                    .get_mut(label)
+                    .expect("just inserted it")
+                    .contents = contents;
+                true
+            }
+        };
+
+        let note = self
+            .notes
+            .get_mut(label)
+            .expect("every reference has a note");
+        let number = note.number;
+
+        if self.sidenotes {
+            // The sidenote itself goes alongside the first reference; any later
+            // references just point back to it.
+            if first {
+                output.push(Event::Html(
+                    format!(
+                        "<label for=\"sn-{n}\" class=\"margin-toggle sidenote-number\"></label>\
+                         <input type=\"checkbox\" id=\"sn-{n}\" class=\"margin-toggle\"/>\
+                         <span class=\"sidenote\">",
+                        n = number
+                    )
+                    .into(),
+                ));
+                output.extend(inline(std::mem::take(&mut note.contents)));
+                output.push(Event::Html("</span>".into()));
+            } else {
+                output.push(Event::Html(
+                    format!(
+                        "<sup class=\"sidenote-reference\"><a href=\"#sn-{n}\">{n}</a></sup>",
+                        n = number
+                    )
+                    .into(),
+                ));
+            }
+        } else {
+            output.push(Event::Html(
+                format!(
+                    "<sup class=\"footnote-reference\"><a href=\"#fn-{n}\" id=\"{id}\" role=\"doc-noteref\">{n}</a></sup>",
+                    n = number,
+                    id = reference_id(number, note.references),
+                )
+                .into(),
+            ));
+        }
+    }
+}
+
+/// Pull every footnote definition out of the events.
+fn take_definitions(events: Vec<Event>) -> (Vec<Event>, HashMap<String, Vec<Event>>) {
+    let mut remaining = Vec::with_capacity(events.len());
+    let mut definitions = HashMap::new();
+    let mut current: Option<(CowStr, Vec<Event>)> = None;
+
+    for event in events {
+        match (event, &mut current) {
+            (Event::Start(Tag::FootnoteDefinition(label)), _) => {
+                current = Some((label, Vec::new()));
+            }
+            (Event::End(Tag::FootnoteDefinition(_)), _) => {
+                if let Some((label, contents)) = current.take() {
+                    definitions.insert(label.to_string(), contents);
+                }
+            }
+            (event, Some((_, contents))) => contents.push(event),
+            (event, None) => remaining.push(event),
+        }
+    }
+
+    (remaining, definitions)
+}
+
+/// Sidenotes live inside a `<span>` in the middle of a paragraph, so they
+/// can't contain blocks: unwrap them, with line breaks between them and
+/// markers for list items. Code blocks keep their markup in a `<span
+/// class="code-block">`, which needs styling as a block to keep its lines.
+fn inline(contents: Vec<Event>) -> Vec<Event> {
+    let mut output = Vec::with_capacity(contents.len());
+    // The next number of each list we're in, or `None` for bulleted lists.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut needs_break = false;
+
+    for event in contents {
+        match event {
+            Event::Start(tag) if super::is_block(&tag) => {
+                needs_break = needs_break || !output.is_empty();
+                match tag {
+                    Tag::List(first) => lists.push(first),
+                    Tag::Item => {
+                        let marker = match lists.last_mut() {
+                            Some(Some(number)) => {
+                                *number += 1;
+                                format!("{}. ", *number - 1)
+                            }
+                            _ => String::from("• "),
+                        };
+                        if needs_break {
+                            output.push(Event::Html("<br>".into()));
+                            needs_break = false;
+                        }
+                        output.push(Event::Text(marker.into()));
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(tag) if super::is_block(&tag) => match tag {
+                Tag::List(_) => {
+                    lists.pop();
+                }
+                Tag::TableCell => output.push(Event::Text(" ".into())),
+                _ => {}
+            },
+            event => {
+                let code_block = matches!(&event, Event::Html(html) if html.starts_with("<pre>"));
+                if needs_break || (code_block && !output.is_empty()) {
+                    output.push(Event::Html("<br>".into()));
+                    needs_break = false;
+                }
+                output.push(match event {
+                    Event::Html(html) => Event::Html(
+                        html.replace("<pre><code", "<span class=\"code-block\"><code")
+                            .replace("</code></pre>", "</code></span>")
+                            .into(),
+                    ),
+                    event => event,
+                });
+            }
+        }
+    }
+
+    output
+}
+
+fn reference_id(number: usize, reference: usize) -> String {
+    if reference == 1 {
+        format!("fnref-{}", number)
+    } else {
+        format!("fnref-{}-{}", number, reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::{html, Options, Parser};
+
+    use crate::page::markdown::citations::Citer;
+
+    fn render(events: Vec<Event>, sidenotes: bool) -> String {
+        let mut rendered = String::new();
+        html::push_html(
+            &mut rendered,
+            process(events, &Footnotes::default(), sidenotes).into_iter(),
+        );
+        rendered
+    }
+
+    fn parse(markdown: &str) -> Vec<Event<'_>> {
+        super::super::merge_text(
+            Parser::new_ext(markdown, Options::ENABLE_FOOTNOTES),
+            markdown,
+        )
+    }
+
+    #[test]
+    fn notes_in_notes() {
+        let markdown =
+            "Text.[^a] More.[^c]\n\n[^a]: A, see[^b].\n\n[^b]: B, and back to A.[^a]\n\n[^c]: C.\n";
+
+        let endnotes = render(parse(markdown), false);
+        assert!(endnotes.contains("<p>Text.<sup class=\"footnote-reference\"><a href=\"#fn-1\""));
+        assert!(endnotes.contains("<p>A, see<sup class=\"footnote-reference\"><a href=\"#fn-2\""));
+        assert!(endnotes.contains("More.<sup class=\"footnote-reference\"><a href=\"#fn-3\""));
+        assert!(endnotes.contains("<a href=\"#fn-1\" id=\"fnref-1-2\""));
+        assert!(!endnotes.contains("class=\"footnote-definition\""));
+
+        let sidenotes = render(parse(markdown), true);
+        assert!(sidenotes.contains(
+            "<span class=\"sidenote\">A, see<label for=\"sn-2\" class=\"margin-toggle sidenote-number\"></label>"
+        ));
+        assert!(sidenotes.contains(
+            "B, and back to A.<sup class=\"sidenote-reference\"><a href=\"#sn-1\">1</a></sup></span>.</span>"
+        ));
+    }
+
+    #[test]
+    fn citations_in_notes() {
+        let citer = Citer::for_tests(
+            "chicago-notes",
+            "@book{barth1936, author = {Barth, Karl}, title = {Church Dogmatics}, year = {1936}}",
+        );
+        let (events, _) = super::super::citations::process(
+            parse("Text.[^a]\n\n[^a]: As Barth says [@barth1936, p. 5].\n"),
+            &citer,
+            None,
+        )
+        .expect("the citation is in the bibliography");
+
+        let endnotes = render(events, false);
+        assert!(endnotes.contains(
+            "<li id=\"fn-1\">\n<p>As Barth says <sup class=\"footnote-reference\"><a href=\"#fn-2\""
+        ));
+        assert!(endnotes.contains("<li id=\"fn-2\">\n<p>Karl Barth, <i>Church Dogmatics</i>"));
+    }
+
+    #[test]
+    fn sidenotes_have_no_blocks() {
+        let mut events = parse("Text.[^a]\n\n[^a]: First.\n");
+        let end = events.pop().expect("the definition ends");
+        events.extend(parse("- one\n- two\n"));
+        // Code blocks are already HTML by now.
+        events.push(Event::Html("<pre><code>".into()));
+        events.push(Event::Text("code\n".into()));
+        events.push(Event::Html("</code></pre>".into()));
+        events.push(end);
+        let sidenotes = render(events, true);
+        assert!(sidenotes.contains(
+            "<span class=\"sidenote\">First.<br>• one<br>• two<br><span class=\"code-block\"><code>code\n</code></span></span>"
+        ));
+    }
+}
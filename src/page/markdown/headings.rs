@@ -0,0 +1,242 @@
+//! Stable IDs for headings, optional self-links, and the table of contents.
+
+use std::collections::HashSet;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{escape::escape_html, Event, Tag};
+use regex::Regex;
+
+use crate::config::Headings;
+
+lazy_static! {
+    /// A Pandoc-style explicit ID at the end of a heading: `## Heading {#id}`.
+    pub(super) static ref EXPLICIT_ID: Regex =
+        Regex::new(r"\s*\{#(?P<id>[^\s{}]+)\}\s*$").expect("heading ID regex is legit");
+}
+
+/// A heading in the document.
+#[derive(Debug, Clone)]
+pub(crate) struct Heading {
+    pub(crate) level: u32,
+    pub(crate) id: String,
+    /// The plain text of the heading.
+    pub(crate) title: String,
+}
+
+/// An entry in the table of contents, with the headings nested under it.
+#[derive(Debug)]
+pub(crate) struct TocEntry {
+    pub(crate) heading: Heading,
+    pub(crate) children: Vec<TocEntry>,
+}
+
+/// Give every heading an ID (explicit via `{#id}`, or else derived from its
+/// text and made unique within the page), add self-links if configured, and
+/// collect the headings in document order. Explicit IDs must be unique, and
+/// derived IDs steer clear of them, wherever they are in the page.
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    config: &Headings,
+) -> Result<(Vec<Event<'a>>, Vec<Heading>), String> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut headings = Vec::new();
+    let mut used_ids = explicit_ids(&events)?;
+
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let level = match event {
+            Event::Start(Tag::Heading(level)) => level,
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut contents = events
+            .by_ref()
+            .take_while(|event| !matches!(event, Event::End(Tag::Heading(..))))
+            .collect::<Vec<_>>();
+
+        let mut explicit_id = None;
+        if let Some(Event::Text(text)) = contents.last_mut() {
+            if let Some(captures) = EXPLICIT_ID.captures(text) {
+                explicit_id = Some(captures["id"].to_string());
+                let trimmed = text[..captures.get(0).expect("always present").start()].to_string();
+                *text = trimmed.into();
+            }
+        }
+
+        let title = contents
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect::<String>()
+            .trim()
+            .to_string();
+
+        let id = match explicit_id {
+            Some(id) => id,
+            None => {
+                let id = unique_id(&title, &used_ids);
+                used_ids.insert(id.clone());
+                id
+            }
+        };
+
+        let mut escaped_id = String::new();
+        escape_html(&mut escaped_id, &id).expect("writing to a String cannot fail");
+
+        output.push(Event::Html(
+            format!("<h{} id=\"{}\">", level, escaped_id).into(),
+        ));
+        output.extend(contents);
+        if config.anchors {
+            output.push(Event::Html(
+                format!(
+                    "<a class=\"{}\" href=\"#{}\" aria-hidden=\"true\">{}</a>",
+                    config.anchor_class, escaped_id, config.anchor_text
+                )
+                .into(),
+            ));
+        }
+        output.push(Event::Html(format!("</h{}>\n", level).into()));
+
+        headings.push(Heading { level, id, title });
+    }
+
+    Ok((output, headings))
+}
+
+/// Split a heading's text into the part before any explicit ID, and the ID
+/// attribute itself, which must reach `process` as written.
+pub(super) fn split_explicit_id(text: &str) -> (&str, &str) {
+    match EXPLICIT_ID.find(text) {
+        Some(found) => text.split_at(found.start()),
+        None => (text, ""),
+    }
+}
+
+/// Every explicit ID in the page, so derived IDs can avoid them.
+fn explicit_ids(events: &[Event]) -> Result<HashSet<String>, String> {
+    let mut ids = HashSet::new();
+    let mut last_text = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(..)) => last_text = None,
+            Event::Text(text) => last_text = Some(text),
+            Event::End(Tag::Heading(..)) => {
+                let id = last_text.and_then(|text| EXPLICIT_ID.captures(text));
+                if let Some(captures) = id {
+                    if !ids.insert(captures["id"].to_string()) {
+                        return Err(format!(
+                            "more than one heading has the ID '{}'",
+                            &captures["id"]
+                        ));
+                    }
+                }
+            }
+            _ => last_text = None,
+        }
+    }
+    Ok(ids)
+}
+
+fn unique_id(title: &str, used: &HashSet<String>) -> String {
+    // Drop apostrophes rather than treating them as word breaks: "it’s", not
+    // "it-s".
+    let base = match slug::slugify(title.replace(&['\'', '’'][..], "")) {
+        slug if slug.is_empty() => String::from("section"),
+        slug => slug,
+    };
+
+    if !used.contains(&base) {
+        return base;
+    }
+
+    (1..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| !used.contains(candidate))
+        .expect("there is always another number")
+}
+
+/// Nest the (flat, in-order) headings into a tree, where each heading owns the
+/// following headings of a deeper level.
+pub(crate) fn table_of_contents(headings: &[Heading]) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+    let mut remaining = headings;
+    while let Some((heading, rest)) = remaining.split_first() {
+        let children_len = rest
+            .iter()
+            .position(|next| next.level <= heading.level)
+            .unwrap_or(rest.len());
+
+        entries.push(TocEntry {
+            heading: heading.clone(),
+            children: table_of_contents(&rest[..children_len]),
+        });
+        remaining = &rest[children_len..];
+    }
+
+    entries
+}
+
+impl TocEntry {
+    /// Render a table of contents as nested ordered lists of links.
+    pub(crate) fn html(entries: &[TocEntry]) -> String {
+        if entries.is_empty() {
+            return String::new();
+        }
+
+        let mut html = String::from("<ol>");
+        for entry in entries {
+            html.push_str("<li><a href=\"#");
+            escape_html(&mut html, &entry.heading.id).expect("writing to a String cannot fail");
+            html.push_str("\">");
+            escape_html(&mut html, &entry.heading.title).expect("writing to a String cannot fail");
+            html.push_str("</a>");
+            html.push_str(&TocEntry::html(&entry.children));
+            html.push_str("</li>");
+        }
+        html.push_str("</ol>");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::Parser;
+
+    fn ids(markdown: &str) -> Result<Vec<String>, String> {
+        let (_, headings) = process(Parser::new(markdown).collect(), &Headings::default())?;
+        Ok(headings.into_iter().map(|heading| heading.id).collect())
+    }
+
+    #[test]
+    fn derived_ids_avoid_later_explicit_ids() {
+        assert_eq!(
+            ids("# Intro\n\n# Intro\n\n# Later {#intro-1}\n").expect("the IDs are unique"),
+            vec!["intro", "intro-2", "intro-1"]
+        );
+    }
+
+    #[test]
+    fn splits_explicit_ids() {
+        assert_eq!(
+            split_explicit_id("Before -- after {#a--b}"),
+            ("Before -- after", " {#a--b}")
+        );
+        assert_eq!(split_explicit_id("No {#id} here"), ("No {#id} here", ""));
+    }
+
+    #[test]
+    fn duplicate_explicit_ids() {
+        assert_eq!(
+            ids("# One {#same}\n\n# Two {#same}\n"),
+            Err(String::from("more than one heading has the ID 'same'"))
+        );
+    }
+}
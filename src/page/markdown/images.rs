@@ -0,0 +1,462 @@
+//! Responsive images: for every local image in the Markdown, generate resized
+//! copies in each configured width and format, and replace the image with a
+//! `<picture>` offering all of them, with the original's intrinsic size so the
+//! page doesn't shift around as they load.
+//!
+//! Encoding images is slow, so every generated file is cached (by a hash of
+//! the original and the encoding settings) in the site's `.cache/images`, and
+//! only copied to the output on later builds.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageReader};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{Event, Tag};
+use sha2::{Digest, Sha256};
+
+use crate::config::{ImageFormat, Images};
+
+use super::links::percent_decode;
+
+/// The generated versions of one image.
+#[derive(Debug)]
+pub(crate) struct Processed {
+    /// The size of the largest variant.
+    width: u32,
+    height: u32,
+    /// For each format, the URL and width of every variant, smallest first.
+    /// The original's format comes first.
+    variants: Vec<(ImageFormat, Vec<(String, u32)>)>,
+}
+
+type Outcome = Result<Option<Arc<Processed>>, String>;
+
+pub(crate) struct ImageProcessor<'c> {
+    config: &'c Images,
+    /// Where absolute image paths (`/images/foo.jpg`) are found.
+    static_dir: PathBuf,
+    output_dir: PathBuf,
+    cache_dir: PathBuf,
+    /// Each image is processed once, however many pages use it, even when the
+    /// pages are rendered at the same time.
+    processed: Mutex<HashMap<PathBuf, Arc<OnceLock<Outcome>>>>,
+}
+
+impl<'c> ImageProcessor<'c> {
+    pub(crate) fn new(config: &'c Images, site_dir: &Path, output_dir: &Path) -> Self {
+        ImageProcessor {
+            config,
+            static_dir: site_dir.join("_static"),
+            output_dir: output_dir.join(&config.output_dir),
+            cache_dir: site_dir.join(".cache/images"),
+            processed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Generate the variants of the image at `path`, or `None` if it isn't in
+    /// a format we can (or should) resize.
+    fn process(&self, path: &Path) -> Outcome {
+        let cell = self
+            .processed
+            .lock()
+            .expect("no other thread panicked while holding the lock")
+            .entry(path.to_path_buf())
+            .or_default()
+            .clone();
+
+        cell.get_or_init(|| self.generate(path).map(|processed| processed.map(Arc::new)))
+            .clone()
+    }
+
+    fn generate(&self, path: &Path) -> Result<Option<Processed>, String> {
+        // GIFs are usually animated, which resizing would throw away, and
+        // SVGs don't need resizing at all.
+        let original_format = match image::ImageFormat::from_path(path) {
+            Ok(image::ImageFormat::Jpeg) => ImageFormat::Jpeg,
+            Ok(image::ImageFormat::Png) => ImageFormat::Png,
+            Ok(image::ImageFormat::WebP) => ImageFormat::Webp,
+            _ => return Ok(None),
+        };
+
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+        let (width, height) = ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .map_err(|e| e.to_string())
+            .and_then(|reader| reader.into_dimensions().map_err(|e| e.to_string()))
+            .map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+
+        let hash = cache_key(&bytes, self.config.quality);
+
+        let stem = path
+            .file_stem()
+            .map(|stem| slug::slugify(stem.to_string_lossy()))
+            .unwrap_or_default();
+
+        let mut widths = self
+            .config
+            .widths
+            .iter()
+            .map(|&target| target.min(width))
+            .collect::<Vec<_>>();
+        if widths.is_empty() {
+            widths.push(width);
+        }
+        widths.sort_unstable();
+        widths.dedup();
+
+        let mut formats = vec![original_format];
+        formats.extend(
+            self.config
+                .formats
+                .iter()
+                .filter(|&&format| format != original_format),
+        );
+
+        std::fs::create_dir_all(&self.cache_dir)
+            .map_err(|e| format!("{}: {}", self.cache_dir.display(), e))?;
+        std::fs::create_dir_all(&self.output_dir)
+            .map_err(|e| format!("{}: {}", self.output_dir.display(), e))?;
+
+        let url_dir = self.config.output_dir.to_string_lossy();
+        let url_dir = url_dir.trim_matches('/');
+        let mut decoded: Option<DynamicImage> = None;
+        let mut variants = Vec::with_capacity(formats.len());
+        for format in formats {
+            let mut urls = Vec::with_capacity(widths.len());
+            for &variant_width in &widths {
+                let name = format!("{}-{}-{}.{}", stem, hash, variant_width, format.extension());
+                let cached = self.cache_dir.join(&name);
+
+                if !cached.exists() {
+                    if decoded.is_none() {
+                        decoded = Some(image::load_from_memory(&bytes).map_err(|e| {
+                            format!("could not decode '{}': {}", path.display(), e)
+                        })?);
+                    }
+                    let image = decoded.as_ref().expect("just decoded it");
+                    let resized = if variant_width == width {
+                        image.clone()
+                    } else {
+                        image.resize_exact(
+                            variant_width,
+                            scaled_height(width, height, variant_width),
+                            FilterType::Lanczos3,
+                        )
+                    };
+
+                    let encoded = encode(&resized, format, self.config.quality)
+                        .map_err(|e| format!("could not encode '{}': {}", name, e))?;
+                    // Another build may be reading the cache at the same time,
+                    // so the image only appears there once it's all written.
+                    let partial = self
+                        .cache_dir
+                        .join(format!(".{}.{}", name, std::process::id()));
+                    std::fs::write(&partial, encoded)
+                        .map_err(|e| format!("{}: {}", partial.display(), e))?;
+                    std::fs::rename(&partial, &cached)
+                        .map_err(|e| format!("{}: {}", cached.display(), e))?;
+                }
+
+                let output = self.output_dir.join(&name);
+                std::fs::copy(&cached, &output)
+                    .map_err(|e| format!("{}: {}", output.display(), e))?;
+                urls.push((format!("/{}/{}", url_dir, name), variant_width));
+            }
+            variants.push((format, urls));
+        }
+
+        let largest = *widths.last().expect("there is always at least one width");
+        Ok(Some(Processed {
+            width: largest,
+            height: scaled_height(width, height, largest),
+            variants,
+        }))
+    }
+}
+
+/// Identifies the variants of an image: anything which changes them must
+/// change this too.
+fn cache_key(bytes: &[u8], quality: u8) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hasher.update([quality]);
+    hasher
+        .finalize()
+        .iter()
+        .take(5)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The height of an image of `width` by `height` scaled to `new_width`.
+fn scaled_height(width: u32, height: u32, new_width: u32) -> u32 {
+    ((f64::from(height) * f64::from(new_width) / f64::from(width)).round() as u32).max(1)
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    match format {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, quality);
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(encoder)
+                .map_err(|e| e.to_string())?;
+        }
+        ImageFormat::Png => {
+            let encoder = image::codecs::png::PngEncoder::new(&mut encoded);
+            image
+                .write_with_encoder(encoder)
+                .map_err(|e| e.to_string())?;
+        }
+        ImageFormat::Webp => {
+            // libwebp only takes 8-bit RGB(A).
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            let encoder = webp::Encoder::from_image(&image)?;
+            encoded.extend_from_slice(&encoder.encode(f32::from(quality)));
+        }
+    }
+    Ok(encoded)
+}
+
+/// Replace every local image with a `<picture>` of its variants. `source` is
+/// the path of the file being rendered, which relative paths are relative to.
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    processor: &ImageProcessor,
+    source: &Path,
+) -> Result<Vec<Event<'a>>, String> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let (dest, title) = match &event {
+            Event::Start(Tag::Image(_, dest, title)) => (dest.clone(), title.clone()),
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let path = match local_path(&dest, source, &processor.static_dir) {
+            Some(path) => path,
+            None => {
+                output.push(event);
+                continue;
+            }
+        };
+        if !path.is_file() {
+            return Err(format!(
+                "image '{}' not found at '{}'",
+                dest,
+                path.display()
+            ));
+        }
+
+        let processed = match processor.process(&path)? {
+            Some(processed) => processed,
+            None => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        // The alt text is everything up to the end of the image, as text.
+        let mut depth = 1;
+        let mut alt = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Start(Tag::Image(..)) => depth += 1,
+                Event::End(Tag::Image(..)) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                _ => {}
+            }
+        }
+
+        output.push(Event::Html(
+            picture(&processed, &alt, &title, &processor.config.sizes).into(),
+        ));
+    }
+
+    Ok(output)
+}
+
+fn local_path(dest: &str, source: &Path, static_dir: &Path) -> Option<PathBuf> {
+    if dest.contains(':') || dest.starts_with("//") {
+        return None;
+    }
+
+    let dest = percent_decode(dest.split(&['?', '#'][..]).next().unwrap_or(dest));
+    Some(match dest.strip_prefix('/') {
+        Some(from_root) => static_dir.join(from_root),
+        None => source.parent()?.join(dest),
+    })
+}
+
+fn picture(processed: &Processed, alt: &str, title: &str, sizes: &str) -> String {
+    let mut escaped_sizes = String::new();
+    escape_html(&mut escaped_sizes, sizes).expect("writing to a String cannot fail");
+    let sizes = escaped_sizes;
+
+    let srcset = |urls: &[(String, u32)]| {
+        let mut srcset = String::new();
+        for (index, (url, width)) in urls.iter().enumerate() {
+            if index > 0 {
+                srcset.push_str(", ");
+            }
+            escape_href(&mut srcset, url).expect("writing to a String cannot fail");
+            srcset.push_str(&format!(" {}w", width));
+        }
+        srcset
+    };
+
+    let mut html = String::from("<picture>");
+    let ((_, fallback), alternatives) = processed
+        .variants
+        .split_first()
+        .expect("there is always the original format");
+    for (format, urls) in alternatives {
+        html.push_str(&format!(
+            "<source type=\"{}\" srcset=\"{}\" sizes=\"{}\">",
+            format.mime_type(),
+            srcset(urls),
+            sizes
+        ));
+    }
+
+    let (largest, _) = fallback.last().expect("there is always at least one width");
+    html.push_str("<img src=\"");
+    escape_href(&mut html, largest).expect("writing to a String cannot fail");
+    html.push_str(&format!(
+        "\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"",
+        srcset(fallback),
+        sizes,
+        processed.width,
+        processed.height
+    ));
+    escape_html(&mut html, alt).expect("writing to a String cannot fail");
+    html.push('"');
+    if !title.is_empty() {
+        html.push_str(" title=\"");
+        escape_html(&mut html, title).expect("writing to a String cannot fail");
+        html.push('"');
+    }
+    html.push_str(" loading=\"lazy\" decoding=\"async\"></picture>");
+
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A site with nothing but a 100×50 PNG, somewhere of its own.
+    fn site(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lx-images-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("can create the test site");
+        image::RgbImage::from_pixel(100, 50, image::Rgb([200, 40, 40]))
+            .save(dir.join("Red Box.png"))
+            .expect("can write the image");
+        dir
+    }
+
+    fn config() -> Images {
+        Images {
+            widths: vec![40, 200, 40],
+            formats: vec![ImageFormat::Webp, ImageFormat::Png],
+            ..Images::default()
+        }
+    }
+
+    #[test]
+    fn cache_key_covers_contents_and_quality() {
+        let key = cache_key(b"image", 80);
+        assert_eq!(key.len(), 10);
+        assert_eq!(key, cache_key(b"image", 80));
+        assert_ne!(key, cache_key(b"image", 81));
+        assert_ne!(key, cache_key(b"other", 80));
+    }
+
+    #[test]
+    fn generates_each_width_in_each_format() {
+        let dir = site("variants");
+        let config = config();
+        let processor = ImageProcessor::new(&config, &dir, &dir.join("out"));
+
+        let processed = processor
+            .process(&dir.join("Red Box.png"))
+            .expect("processes")
+            .expect("is resizable");
+        assert_eq!((processed.width, processed.height), (100, 50));
+
+        let key = cache_key(&std::fs::read(dir.join("Red Box.png")).expect("reads"), 80);
+        let url = |width: u32, extension: &str| {
+            (
+                format!("/images/red-box-{}-{}.{}", key, width, extension),
+                width,
+            )
+        };
+        assert_eq!(
+            processed.variants,
+            vec![
+                (ImageFormat::Png, vec![url(40, "png"), url(100, "png")]),
+                (ImageFormat::Webp, vec![url(40, "webp"), url(100, "webp")]),
+            ]
+        );
+
+        let small = image::open(dir.join(format!("out/images/red-box-{}-40.png", key)))
+            .expect("is in the output");
+        assert_eq!((small.width(), small.height()), (40, 20));
+
+        // Nothing half-written is left behind in the cache.
+        let mut cached = std::fs::read_dir(dir.join(".cache/images"))
+            .expect("has a cache")
+            .map(|entry| entry.expect("can list").file_name())
+            .collect::<Vec<_>>();
+        cached.sort();
+        assert_eq!(cached.len(), 4);
+        assert!(cached
+            .iter()
+            .all(|name| !name.to_string_lossy().starts_with('.')));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn later_builds_copy_from_the_cache() {
+        let dir = site("cache");
+        let config = config();
+        ImageProcessor::new(&config, &dir, &dir.join("out"))
+            .process(&dir.join("Red Box.png"))
+            .expect("processes");
+
+        let key = cache_key(&std::fs::read(dir.join("Red Box.png")).expect("reads"), 80);
+        let name = format!("red-box-{}-40.webp", key);
+        std::fs::write(dir.join(".cache/images").join(&name), "cached").expect("can write");
+        std::fs::remove_dir_all(dir.join("out")).expect("can clean the output");
+
+        ImageProcessor::new(&config, &dir, &dir.join("out"))
+            .process(&dir.join("Red Box.png"))
+            .expect("processes");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out/images").join(&name)).ok(),
+            Some(String::from("cached"))
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
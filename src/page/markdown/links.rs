@@ -0,0 +1,507 @@
+//! Links to other pages by their *source*, rather than their URL: either a
+//! normal Markdown link to a Markdown file, like `[see](../2019/foo.md)`, or a
+//! wiki-style `[[Foo]]` (or `[[Foo|some text]]`), naming a page by its file name
+//! or title. Either may point at a heading with `#id`.
+//!
+//! A page's URL depends on every other page's metadata, so we can't know it
+//! while rendering a single page. Instead, each such link gets a placeholder
+//! `href`, and once every page has been rendered, `Index::resolve` swaps the
+//! placeholders for the targets' URLs.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{CowStr, Event, LinkType, Tag};
+use regex::Regex;
+
+use crate::config::Config;
+use crate::page::metadata::Metadata;
+use crate::page::{Page, Source};
+
+use super::math::{self, Segment};
+
+const PLACEHOLDER: &str = "lx-link:";
+
+lazy_static! {
+    /// `[[Target]]`, `[[Target#id]]`, or `[[Target|text]]`.
+    static ref WIKILINK: Regex =
+        Regex::new(r"\[\[(?P<target>[^\[\]|#]+)(?:#(?P<fragment>[^\[\]|]+))?(?:\|(?P<text>[^\[\]]+))?\]\]")
+            .expect("wikilink regex is legit");
+
+    /// The end of a sentence, including any closing quotes or brackets.
+    static ref SENTENCE_END: Regex =
+        Regex::new(r#"[.!?…]["'”’)\]]*\s+"#).expect("sentence end regex is legit");
+
+    static ref RESOLVED: Regex =
+        Regex::new(&format!(r"{}(?P<index>\d+)", PLACEHOLDER)).expect("placeholder regex is legit");
+}
+
+/// A link to another page, waiting to be resolved.
+#[derive(Debug, Clone)]
+pub(crate) struct InternalLink {
+    pub(crate) target: Target,
+    pub(crate) fragment: Option<String>,
+    /// The sentence containing the link, as plain text.
+    pub(crate) context: String,
+    /// The source of the page the link points to, once resolved.
+    pub(crate) resolved: Option<PathBuf>,
+    /// The link as written, for error messages.
+    written: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Target {
+    /// The (normalized) path of the source file.
+    Path(PathBuf),
+    /// A page's file name (without extension) or title.
+    Name(String),
+}
+
+/// Give every internal link in the events a placeholder `href`, and collect
+/// the links in order. `source` is the path of the file being rendered, and
+/// `root_dir` the content directory, for links like `/journal/foo.md`.
+pub(super) fn process<'a>(
+    events: Vec<Event<'a>>,
+    source: &Path,
+    root_dir: &Path,
+) -> (Vec<Event<'a>>, Vec<InternalLink>) {
+    let mut links = Vec::new();
+    let mut output = Vec::with_capacity(events.len());
+    let mut in_code_block = false;
+    // The destination of each link we're inside, since end tags must match.
+    let mut open_links = Vec::new();
+    // The plain text of the current block so far, and where in it each of the
+    // block's links are, to find the sentence around each link.
+    let mut block_text = String::new();
+    let mut in_block = Vec::new();
+
+    for event in events {
+        match &event {
+            Event::Start(tag) | Event::End(tag) if is_block(tag) => {
+                for (index, start, end) in in_block.drain(..) {
+                    let link: &mut InternalLink = &mut links[index];
+                    link.context = sentence(&block_text, start, end);
+                }
+                block_text.clear();
+            }
+            // Wikilinks are added below, as they're replaced.
+            Event::Text(text) if in_code_block || !text.contains("[[") => {
+                push_plain(&mut block_text, text)
+            }
+            Event::Code(text) => push_plain(&mut block_text, text),
+            Event::SoftBreak | Event::HardBreak => block_text.push(' '),
+            _ => {}
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(..)) => {
+                in_code_block = true;
+                output.push(event);
+            }
+            Event::End(Tag::CodeBlock(..)) => {
+                in_code_block = false;
+                output.push(event);
+            }
+            Event::Start(Tag::Link(link_type, dest, title)) => {
+                let (dest, index) = match internal_link(&dest, source, root_dir) {
+                    Some(link) => {
+                        links.push(link);
+                        (placeholder(links.len() - 1), Some(links.len() - 1))
+                    }
+                    None => (dest, None),
+                };
+                open_links.push((dest.clone(), index, block_text.len()));
+                output.push(Event::Start(Tag::Link(link_type, dest, title)));
+            }
+            Event::End(Tag::Link(link_type, dest, title)) => {
+                let dest = match open_links.pop() {
+                    Some((dest, index, start)) => {
+                        if let Some(index) = index {
+                            in_block.push((index, start, block_text.len()));
+                        }
+                        dest
+                    }
+                    None => dest,
+                };
+                output.push(Event::End(Tag::Link(link_type, dest, title)));
+            }
+            Event::Text(text) if !in_code_block && text.contains("[[") => {
+                let mut rest = text.as_ref();
+                while let Some(captures) = WIKILINK.captures(rest) {
+                    let whole = captures.get(0).expect("always present");
+                    if whole.start() > 0 {
+                        push_plain(&mut block_text, &rest[..whole.start()]);
+                        output.push(Event::Text(rest[..whole.start()].to_string().into()));
+                    }
+
+                    let target = captures["target"].trim();
+                    let label = captures
+                        .name("text")
+                        .map_or(target, |text| text.as_str().trim());
+                    let dest = placeholder(links.len());
+                    let start = block_text.len();
+                    push_plain(&mut block_text, label);
+                    in_block.push((links.len(), start, block_text.len()));
+                    links.push(InternalLink {
+                        target: Target::Name(target.to_string()),
+                        fragment: captures.name("fragment").map(|f| f.as_str().to_string()),
+                        context: String::new(),
+                        resolved: None,
+                        written: whole.as_str().to_string(),
+                    });
+
+                    let tag = Tag::Link(LinkType::Inline, dest, "".into());
+                    output.push(Event::Start(tag.clone()));
+                    output.push(Event::Text(label.to_string().into()));
+                    output.push(Event::End(tag));
+
+                    rest = &rest[whole.end()..];
+                }
+
+                if !rest.is_empty() {
+                    push_plain(&mut block_text, rest);
+                    output.push(Event::Text(rest.to_string().into()));
+                }
+            }
+            _ => output.push(event),
+        }
+    }
+
+    (output, links)
+}
+
+/// Blocks delimit the text searched for a link's sentence.
+fn is_block(tag: &Tag) -> bool {
+    !matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+/// Add text to the block's text, leaving out any math.
+fn push_plain(block_text: &mut String, text: &str) {
+    for segment in math::segments(text) {
+        if let Segment::Text(text) = segment {
+            block_text.push_str(text);
+        }
+    }
+}
+
+/// The sentence in `text` containing the range from `start` to `end`.
+fn sentence(text: &str, start: usize, end: usize) -> String {
+    let begin = SENTENCE_END
+        .find_iter(&text[..start])
+        .last()
+        .map_or(0, |found| found.end());
+    let finish = SENTENCE_END
+        .find_at(text, end)
+        .map_or(text.len(), |found| found.end());
+
+    text[begin..finish]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn placeholder(index: usize) -> CowStr<'static> {
+    format!("{}{}", PLACEHOLDER, index).into()
+}
+
+/// If `dest` is a relative link to a Markdown file, where that file is.
+fn internal_link(dest: &str, source: &Path, root_dir: &Path) -> Option<InternalLink> {
+    // Anything with a scheme (`https:`, `mailto:`, ...) is external.
+    if dest.contains(':') {
+        return None;
+    }
+
+    let (path, fragment) = match dest.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment.to_string())),
+        None => (dest, None),
+    };
+    if !path.ends_with(".md") {
+        return None;
+    }
+
+    let path = percent_decode(path);
+    let path = match path.strip_prefix('/') {
+        Some(from_root) => root_dir.join(from_root),
+        None => source.parent().unwrap_or(root_dir).join(path),
+    };
+
+    Some(InternalLink {
+        target: Target::Path(normalize(&path)),
+        fragment,
+        context: String::new(),
+        resolved: None,
+        written: dest.to_string(),
+    })
+}
+
+/// Markdown link destinations may percent-encode characters like spaces, which
+/// are common in our file names.
+pub(crate) fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| path.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Resolve `.` and `..` without touching the file system, so that paths to
+/// files which don't exist can still be reported (and compared) sensibly.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Every page's URL, by source path, file name, and title. Pages which aren't
+/// published have no URL, but are still known, so links to them can say so.
+#[derive(Default)]
+pub(crate) struct Index {
+    by_path: HashMap<PathBuf, Option<String>>,
+    by_name: HashMap<String, Vec<(PathBuf, Option<String>)>>,
+}
+
+impl Index {
+    pub(crate) fn new(
+        pages: &[Page],
+        unpublished: &[(Source, Metadata)],
+        config: &Config,
+    ) -> Index {
+        let mut index = Index::default();
+        for page in pages {
+            index.insert(&page.source, page.metadata.title(), Some(page.url(config)));
+        }
+        for (source, metadata) in unpublished {
+            index.insert(&source.path, metadata.title(), None);
+        }
+        index
+    }
+
+    fn insert(&mut self, source: &Path, title: Option<&str>, url: Option<String>) {
+        let path = normalize(source);
+        self.by_path.insert(path.clone(), url.clone());
+
+        let stem = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string());
+        let mut names = stem
+            .into_iter()
+            .chain(title.map(String::from))
+            .collect::<Vec<_>>();
+        names.dedup();
+        for name in names {
+            self.by_name
+                .entry(name)
+                .or_default()
+                .push((path.clone(), url.clone()));
+        }
+    }
+
+    /// The source path and URL of the page a link points to.
+    pub(crate) fn find(&self, link: &InternalLink) -> Result<(PathBuf, String), String> {
+        let (path, url) = match &link.target {
+            Target::Path(path) => self
+                .by_path
+                .get(path)
+                .map(|url| (path.clone(), url.clone()))
+                .ok_or_else(|| format!("link to '{}': no such page", link.written))?,
+
+            Target::Name(name) => match self.by_name.get(name).map(Vec::as_slice) {
+                Some([found]) => found.clone(),
+                Some(candidates) if !candidates.is_empty() => {
+                    let mut paths = candidates
+                        .iter()
+                        .map(|(path, _)| path.display().to_string())
+                        .collect::<Vec<_>>();
+                    paths.sort_unstable();
+                    return Err(format!(
+                        "link to '{}' is ambiguous; it could be any of:\n\t{}",
+                        link.written,
+                        paths.join("\n\t")
+                    ));
+                }
+                _ => return Err(format!("link to '{}': no such page", link.written)),
+            },
+        };
+
+        let url = url.ok_or_else(|| {
+            format!(
+                "link to '{}': '{}' is not published",
+                link.written,
+                path.display()
+            )
+        })?;
+        let url = match &link.fragment {
+            Some(fragment) => format!("{}#{}", url, fragment),
+            None => url,
+        };
+        Ok((path, url))
+    }
+
+    /// Replace the placeholders in a page's contents with the URLs of its
+    /// links' targets, reporting every link which doesn't resolve.
+    pub(crate) fn resolve(&self, page: &mut Page) -> Result<(), String> {
+        if page.links.is_empty() {
+            return Ok(());
+        }
+
+        let (found, errors): (Vec<_>, Vec<_>) = page
+            .links
+            .iter()
+            .map(|link| self.find(link))
+            .partition(Result::is_ok);
+        if !errors.is_empty() {
+            return Err(errors
+                .into_iter()
+                .filter_map(Result::err)
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+
+        let mut urls = Vec::with_capacity(found.len());
+        for (link, (path, url)) in page
+            .links
+            .iter_mut()
+            .zip(found.into_iter().filter_map(Result::ok))
+        {
+            link.resolved = Some(path);
+            urls.push(url);
+        }
+
+        let fill = |html: &str| {
+            RESOLVED
+                .replace_all(html, |captures: &regex::Captures| {
+                    captures["index"]
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| urls.get(index))
+                        .map_or_else(|| captures[0].to_string(), |url| escape_url(url))
+                })
+                .to_string()
+        };
+        page.contents = fill(&page.contents);
+        page.text = RESOLVED
+            .replace_all(&page.text, |captures: &regex::Captures| {
+                captures["index"]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| urls.get(index))
+                    .map_or_else(|| captures[0].to_string(), String::clone)
+            })
+            .to_string();
+        // Summaries taken from the page have the same links.
+        if let Some(summary) = &mut page.summary {
+            summary.html = fill(&summary.html);
+        }
+
+        Ok(())
+    }
+}
+
+/// The placeholder is inside an `href` attribute which pulldown-cmark already
+/// escaped, so the URL going in its place must be escaped too.
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    pulldown_cmark::escape::escape_href(&mut escaped, url)
+        .expect("writing to a String cannot fail");
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::Parser;
+
+    use super::*;
+
+    fn links(markdown: &str) -> Vec<InternalLink> {
+        let events = super::super::merge_text(Parser::new(markdown), markdown);
+        process(
+            events,
+            Path::new("/site/content/here.md"),
+            Path::new("/site/content"),
+        )
+        .1
+    }
+
+    fn index() -> Index {
+        let mut index = Index::default();
+        for (source, title, url) in [
+            ("/site/content/a.md", Some("Alpha"), Some("/a")),
+            ("/site/content/notes/b.md", None, Some("/notes/b")),
+            ("/site/content/notes/dup.md", None, Some("/notes/dup")),
+            ("/site/content/other/dup.md", None, Some("/other/dup")),
+            ("/site/content/draft.md", Some("Draft"), None),
+        ] {
+            index.insert(Path::new(source), title, url.map(String::from));
+        }
+        index
+    }
+
+    #[test]
+    fn finds_pages_by_path_name_and_title() {
+        let index = index();
+        let found = links("[one](a.md#intro), [[b]], [[Alpha|the first]], [two](/notes/b.md)")
+            .iter()
+            .map(|link| index.find(link).map(|(_, url)| url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                Ok(String::from("/a#intro")),
+                Ok(String::from("/notes/b")),
+                Ok(String::from("/a")),
+                Ok(String::from("/notes/b")),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_links_which_do_not_resolve() {
+        let index = index();
+        let errors = links("[[missing]], [[dup]], [[Draft]], [gone](gone.md)")
+            .iter()
+            .map(|link| index.find(link).map(|(_, url)| url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                Err(String::from("link to '[[missing]]': no such page")),
+                Err(String::from(
+                    "link to '[[dup]]' is ambiguous; it could be any of:\n\t\
+                     /site/content/notes/dup.md\n\t/site/content/other/dup.md"
+                )),
+                Err(String::from(
+                    "link to '[[Draft]]': '/site/content/draft.md' is not published"
+                )),
+                Err(String::from("link to 'gone.md': no such page")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! TeX math, rendered to MathML (or KaTeX's HTML) at build time, so pages need
+//! no client-side JavaScript to display it.
+//!
+//! Inline `$...$` and display `$$...$$` math are pulled out of the source
+//! *before* it is parsed as Markdown, because Markdown's backslash escapes
+//! would otherwise eat TeX's backslashes. Each is replaced by a placeholder
+//! which `render_markdown` then swaps for the rendered math. ```` ```math ````
+//! blocks are already safe from Markdown, so they go straight to `render`.
+
+use std::borrow::Cow;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::config::{Math, MathOutput};
+
+/// Placeholders are the index of the math, wrapped in these private-use
+/// characters, which nothing in Markdown (or the typography pass) touches.
+const OPEN: char = '\u{E000}';
+const CLOSE: char = '\u{E001}';
+
+/// A source with its math swapped out for placeholders.
+pub(super) struct Extracted<'s> {
+    pub(super) source: Cow<'s, str>,
+    /// The original TeX for each placeholder.
+    pub(super) tex: Vec<String>,
+    /// The rendered math for each placeholder.
+    pub(super) rendered: Vec<String>,
+    /// Where each placeholder ends in `source`, and how many line breaks the
+    /// math it replaced had, so offsets in `source` can still be turned into
+    /// lines of the original.
+    line_breaks: Vec<(usize, usize)>,
+    first_line: usize,
+}
+
+impl Extracted<'_> {
+    /// The line of the original file holding what is at `offset` in `source`.
+    pub(super) fn line(&self, offset: usize) -> usize {
+        let removed = self
+            .line_breaks
+            .iter()
+            .take_while(|&&(end, _)| end <= offset)
+            .map(|&(_, count)| count)
+            .sum::<usize>();
+        self.first_line + self.source[..offset].matches('\n').count() + removed
+    }
+}
+
+/// A run of text containing placeholders, split into its parts.
+pub(super) enum Segment<'t> {
+    Text(&'t str),
+    Math(usize),
+}
+
+/// Find, render, and replace the math in `src`. `first_line` is the line of the
+/// file on which `src` begins, so errors can point at the right place.
+pub(super) fn extract<'s>(
+    src: &'s str,
+    config: &Math,
+    first_line: usize,
+) -> Result<Extracted<'s>, String> {
+    let mut extracted = Extracted {
+        source: Cow::Borrowed(src),
+        tex: Vec::new(),
+        rendered: Vec::new(),
+        line_breaks: Vec::new(),
+        first_line,
+    };
+
+    if !src.contains('$') {
+        return Ok(extracted);
+    }
+
+    let protected = protected_ranges(src);
+    let mut next_protected = 0;
+    let mut output = String::with_capacity(src.len());
+    let mut copied_to = 0;
+    let mut index = 0;
+
+    while index < src.len() {
+        while next_protected < protected.len() && protected[next_protected].1 <= index {
+            next_protected += 1;
+        }
+        let protected_start = protected
+            .get(next_protected)
+            .map_or(src.len(), |&(start, _)| start);
+        if protected_start <= index {
+            index = protected[next_protected].1;
+            continue;
+        }
+
+        let rest = &src[index..];
+        let c = rest.chars().next().expect("index is always in bounds");
+        if c == '\\' {
+            index += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+
+        if c != '$' {
+            index += c.len_utf8();
+            continue;
+        }
+
+        let found = if let Some(display) = rest.strip_prefix("$$") {
+            display
+                .find("$$")
+                .map(|len| (&display[..len], 2 + len + 2, true))
+        } else {
+            find_inline(rest).map(|len| (&rest[1..1 + len], 1 + len + 1, false))
+        };
+
+        match found {
+            Some((tex, len, display)) if index + len <= protected_start => {
+                let line = first_line + src[..index].matches('\n').count();
+                let rendered = render(tex, display, config)
+                    .map_err(|e| format!("line {}: could not render math: {}", line, e))?;
+
+                output.push_str(&src[copied_to..index]);
+                output.push(OPEN);
+                output.push_str(&extracted.rendered.len().to_string());
+                output.push(CLOSE);
+                extracted
+                    .line_breaks
+                    .push((output.len(), src[index..index + len].matches('\n').count()));
+                extracted.tex.push(tex.to_string());
+                extracted.rendered.push(rendered);
+
+                index += len;
+                copied_to = index;
+            }
+            _ => index += 1,
+        }
+    }
+
+    if !extracted.rendered.is_empty() {
+        output.push_str(&src[copied_to..]);
+        extracted.source = Cow::Owned(output);
+    }
+
+    Ok(extracted)
+}
+
+/// Render a single piece of TeX.
+pub(super) fn render(tex: &str, display: bool, config: &Math) -> Result<String, String> {
+    let mut opts = katex::Opts::default();
+    opts.set_display_mode(display);
+    opts.set_throw_on_error(true);
+    opts.set_output_type(match config.output {
+        MathOutput::MathML => katex::OutputType::Mathml,
+        MathOutput::Html => katex::OutputType::HtmlAndMathml,
+    });
+    for (name, definition) in &config.macros {
+        opts.add_macro(name.clone(), definition.clone());
+    }
+
+    katex::render_with_opts(tex, &opts).map_err(|e| match e {
+        // KaTeX's own parse errors are much more useful without the wrapper,
+        // which is the `Debug` output of the JS engine's error value.
+        katex::Error::JsExecError(detail) => match detail.find("KaTeX parse error: ") {
+            Some(start) => detail[start..]
+                .trim_end_matches("\")")
+                .replace("\\\\", "\\")
+                .replace("\\\"", "\""),
+            None => detail,
+        },
+        other => other.to_string(),
+    })
+}
+
+/// Split text on math placeholders.
+pub(super) fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        let end = match rest[start..].find(CLOSE) {
+            Some(len) => start + len,
+            None => break,
+        };
+
+        match rest[start + OPEN.len_utf8()..end].parse() {
+            Ok(index) => {
+                if start > 0 {
+                    segments.push(Segment::Text(&rest[..start]));
+                }
+                segments.push(Segment::Math(index));
+            }
+            Err(_) => segments.push(Segment::Text(&rest[..end + CLOSE.len_utf8()])),
+        }
+
+        rest = &rest[end + CLOSE.len_utf8()..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    segments
+}
+
+/// Given text starting with a `$`, find the length of the inline math it opens,
+/// if any. Following Pandoc, the opening `$` must have a non-space character
+/// right after it, and the closing `$` must have a non-space character right
+/// before it and must not be followed by a digit (so "$5 and $10" is not math).
+/// Inline math never spans a blank line.
+fn find_inline(text: &str) -> Option<usize> {
+    let body = &text[1..];
+    if body.chars().next().is_none_or(char::is_whitespace) {
+        return None;
+    }
+
+    let mut previous = '$';
+    let mut escaped = false;
+    for (index, c) in body.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '$' {
+            let followed_by_digit = body[index + 1..]
+                .chars()
+                .next()
+                .is_some_and(|next| next.is_ascii_digit());
+            return if index > 0 && !previous.is_whitespace() && !followed_by_digit {
+                Some(index)
+            } else {
+                None
+            };
+        } else if c == '\n' && previous == '\n' {
+            return None;
+        }
+
+        if c != '\r' {
+            previous = c;
+        }
+    }
+
+    None
+}
+
+/// The (merged, sorted) byte ranges of code and raw HTML, where a `$` is just a
+/// dollar sign.
+pub(super) fn protected_ranges(src: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Parser::new_ext(src, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(..)) | Event::Code(..) | Event::Html(..) => {
+                Some((range.start, range.end))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placeholder(index: usize) -> String {
+        format!("{}{}{}", OPEN, index, CLOSE)
+    }
+
+    #[test]
+    fn inline_and_display() {
+        let config = Math::default();
+        let src = "Inline $x^2$ and\n\n$$\ny\n$$\n";
+        let extracted = extract(src, &config, 1).expect("renders");
+
+        assert_eq!(
+            extracted.source,
+            format!("Inline {} and\n\n{}\n", placeholder(0), placeholder(1))
+        );
+        assert_eq!(extracted.tex, vec!["x^2", "\ny\n"]);
+        assert!(extracted.rendered[0].contains("<math"));
+        assert!(!extracted.rendered[0].contains("display=\"block\""));
+        assert!(extracted.rendered[1].contains("display=\"block\""));
+    }
+
+    #[test]
+    fn dollar_signs_which_are_not_math() {
+        let config = Math::default();
+        for src in [
+            r"It costs \$x$ here",
+            "Between $5 and $6.",
+            "Code like `$x$` is code.",
+            "A $ sign, alone.",
+        ] {
+            let extracted = extract(src, &config, 1).expect("renders");
+            assert_eq!(extracted.source, src);
+            assert!(extracted.tex.is_empty(), "{} has no math", src);
+        }
+    }
+
+    #[test]
+    fn errors_and_lines() {
+        let config = Math::default();
+        let error = extract("One\n\nTwo $\\frac{$ three", &config, 10)
+            .err()
+            .expect("does not render");
+        assert!(
+            error.starts_with("line 12: could not render math: KaTeX parse error:"),
+            "{}",
+            error
+        );
+
+        // Lines after multi-line display math still count from the original.
+        let extracted = extract("$$\na\nb\n$$\n\nAfter", &config, 1).expect("renders");
+        let after = extracted.source.find("After").expect("is there");
+        assert_eq!(extracted.line(after), 6);
+    }
+}
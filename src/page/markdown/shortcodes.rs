@@ -0,0 +1,376 @@
+//! Shortcodes: site-defined components, written in Markdown as
+//!
+//! ```text
+//! {% quote source="Karl Barth" page="XI" %}
+//!
+//! > In these texts, God teaches us...
+//!
+//! {% endquote %}
+//! ```
+//!
+//! Each shortcode is a template in the site's `_ui/shortcodes` directory, named
+//! for the file (so `quote.html` defines `quote`). Its arguments are available
+//! to the template as variables, and the Markdown between the opening and
+//! closing tags as `body`. A template which doesn't use `body` defines a
+//! shortcode which stands alone, with no closing tag.
+//!
+//! Shortcode tags must be on lines of their own, and tags which don't name a
+//! shortcode are left alone. The body is not rendered separately: the tags are
+//! swapped for placeholders *before* the Markdown is parsed, and the
+//! placeholders for the template's output on either side of `body` afterward,
+//! so that the body takes part in footnote numbering, heading IDs, and so on
+//! just like the rest of the page.
+//!
+//! Templates can refer to static assets with `asset("/fonts/body.woff2")`,
+//! which gives the URL the asset is actually copied to.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use minijinja::{AutoEscape, Environment, Error, ErrorKind, Value};
+use regex::Regex;
+
+use crate::assets::Manifest;
+
+use super::blocks::Blocks;
+use super::math;
+
+/// Stands in for the body while rendering a template, so we can split the
+/// output around it.
+const BODY: &str = "\u{E004}body\u{E004}";
+
+lazy_static! {
+    /// A shortcode tag alone on a line, possibly inside a block quote.
+    static ref TAG: Regex = Regex::new(
+        r"^(?P<prefix>[ ]{0,3}(?:>[ \t]*)*)\{%\s*(?P<name>[A-Za-z][\w-]*)(?P<args>.*?)\s*%\}[ \t]*$"
+    )
+    .expect("shortcode regex is legit");
+
+    /// A single `key="value"`, `key='value'`, or `key=value` argument.
+    static ref ARGUMENT: Regex = Regex::new(
+        r#"^\s*(?P<key>[A-Za-z_]\w*)\s*=\s*(?:"(?P<double>[^"]*)"|'(?P<single>[^']*)'|(?P<bare>[^\s"']+))"#
+    )
+    .expect("shortcode argument regex is legit");
+}
+
+/// The site's shortcode templates.
+pub(crate) struct Shortcodes {
+    env: Environment<'static>,
+}
+
+impl Shortcodes {
+    /// Load every template in `dir`. A site without the directory simply has
+    /// no shortcodes.
+    pub(crate) fn load(dir: &Path, assets: &Manifest) -> Result<Shortcodes, String> {
+        let mut env = Environment::new();
+        // Shortcodes are always HTML, whatever their templates are called.
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+
+        let urls = assets.urls().clone();
+        env.add_function("asset", move |path: &str| {
+            urls.get(path.trim_start_matches('/'))
+                .cloned()
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidOperation, format!("no asset '{}'", path))
+                })
+        });
+
+        if !dir.is_dir() {
+            return Ok(Shortcodes { env });
+        }
+
+        let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for entry in entries {
+            let path = entry
+                .map_err(|e| format!("{}: {}", dir.display(), e))?
+                .path();
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) if path.is_file() && !name.starts_with('.') => name.to_string(),
+                _ => continue,
+            };
+
+            let template = std::fs::read_to_string(&path)
+                .map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+            env.add_template_owned(name, template)
+                .map_err(|e| format!("could not load shortcode '{}': {}", path.display(), e))?;
+        }
+
+        Ok(Shortcodes { env })
+    }
+
+    fn exists(&self, name: &str) -> bool {
+        self.env.get_template(name).is_ok()
+    }
+
+    /// Render a shortcode, split around its body if it has one.
+    fn render(&self, name: &str, arguments: BTreeMap<String, Value>) -> Result<Rendered, String> {
+        let mut context = arguments;
+        context.insert(String::from("body"), Value::from_safe_string(BODY.into()));
+
+        let output = self
+            .env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|e| format!("could not render shortcode '{}': {}", name, e))?;
+
+        match output.split(BODY).collect::<Vec<_>>().as_slice() {
+            [standalone] => Ok(Rendered::Standalone(standalone.to_string())),
+            [before, after] => Ok(Rendered::Wrapping(before.to_string(), after.to_string())),
+            _ => Err(format!("shortcode '{}' uses its body more than once", name)),
+        }
+    }
+}
+
+enum Rendered {
+    Standalone(String),
+    Wrapping(String, String),
+}
+
+/// A shortcode waiting for its closing tag.
+struct Open {
+    name: String,
+    line: usize,
+    after: String,
+}
+
+/// Find and render the shortcodes in `src`, swapping their tags for `blocks`
+/// placeholders. `first_line` is the line of the file on which `src` begins, so
+/// errors can point at the right place.
+pub(super) fn extract<'s>(
+    src: &'s str,
+    shortcodes: &Shortcodes,
+    first_line: usize,
+    blocks: &mut Blocks,
+) -> Result<Cow<'s, str>, String> {
+    if !src.contains("{%") {
+        return Ok(Cow::Borrowed(src));
+    }
+
+    let protected = math::protected_ranges(src);
+    let mut output = String::with_capacity(src.len());
+    let mut open: Vec<Open> = Vec::new();
+    let mut start = 0;
+    let mut replaced = false;
+
+    for (index, line) in src.split_inclusive('\n').enumerate() {
+        let line_number = first_line + index;
+        let line_start = start;
+        start += line.len();
+
+        let captures = match TAG.captures(line.trim_end_matches(&['\n', '\r'][..])) {
+            Some(captures)
+                if !protected
+                    .iter()
+                    .any(|&(start, end)| start <= line_start && line_start < end) =>
+            {
+                captures
+            }
+            _ => {
+                output.push_str(line);
+                continue;
+            }
+        };
+
+        let name = &captures["name"];
+        let html = if shortcodes.exists(name) {
+            let arguments = parse_arguments(&captures["args"])
+                .map_err(|e| format!("line {}: {}", line_number, e))?;
+            match shortcodes
+                .render(name, arguments)
+                .map_err(|e| format!("line {}: {}", line_number, e))?
+            {
+                Rendered::Standalone(html) => html,
+                Rendered::Wrapping(before, after) => {
+                    open.push(Open {
+                        name: name.to_string(),
+                        line: line_number,
+                        after,
+                    });
+                    before
+                }
+            }
+        } else if let Some(closed) = name
+            .strip_prefix("end")
+            .filter(|closed| shortcodes.exists(closed))
+        {
+            match open.pop() {
+                Some(shortcode) if shortcode.name == closed => shortcode.after,
+                Some(shortcode) => {
+                    return Err(format!(
+                        "line {}: '{{% {} %}}' does not close '{{% {} %}}' from line {}",
+                        line_number, name, shortcode.name, shortcode.line
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "line {}: '{{% {} %}}' has no opening '{{% {} %}}'",
+                        line_number, name, closed
+                    ))
+                }
+            }
+        } else {
+            // Not ours: perhaps something for the page's own template.
+            output.push_str(line);
+            continue;
+        };
+
+        output.push_str(&captures["prefix"]);
+        blocks.insert(&mut output, html);
+        if line.ends_with('\n') {
+            output.push('\n');
+        }
+        replaced = true;
+    }
+
+    if let Some(shortcode) = open.pop() {
+        return Err(format!(
+            "line {}: '{{% {} %}}' is never closed with '{{% end{} %}}'",
+            shortcode.line, shortcode.name, shortcode.name
+        ));
+    }
+
+    Ok(if replaced {
+        Cow::Owned(output)
+    } else {
+        Cow::Borrowed(src)
+    })
+}
+
+fn parse_arguments(mut args: &str) -> Result<BTreeMap<String, Value>, String> {
+    let mut arguments = BTreeMap::new();
+    while !args.trim().is_empty() {
+        let captures = ARGUMENT
+            .captures(args)
+            .ok_or_else(|| format!("could not parse shortcode arguments at '{}'", args.trim()))?;
+
+        let value = captures
+            .name("double")
+            .or_else(|| captures.name("single"))
+            .or_else(|| captures.name("bare"))
+            .expect("one of the alternatives always matches")
+            .as_str();
+        arguments.insert(captures["key"].to_string(), Value::from(value));
+
+        args = &args[captures.get(0).expect("always present").end()..];
+    }
+
+    Ok(arguments)
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+
+    fn shortcodes() -> Shortcodes {
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        for (name, template) in [
+            ("hr", "<hr class=\"{{ class }}\">"),
+            (
+                "quote",
+                "<figure><blockquote>{{ body }}</blockquote><figcaption>{{ source }}</figcaption></figure>",
+            ),
+            ("box", "<div class=\"box\">{{ body }}</div>"),
+        ] {
+            env.add_template_owned(name, template)
+                .expect("test template is legit");
+        }
+        Shortcodes { env }
+    }
+
+    fn render(src: &str) -> Result<String, String> {
+        let mut blocks = Blocks::default();
+        let extracted = extract(src, &shortcodes(), 1, &mut blocks)?;
+        let mut html = String::new();
+        html::push_html(
+            &mut html,
+            blocks
+                .replace(Parser::new(&extracted).collect())
+                .into_iter(),
+        );
+        Ok(html)
+    }
+
+    #[test]
+    fn arguments() {
+        let arguments = parse_arguments(r#" a="x y"  b='it"s' c=3 "#).expect("parses");
+        assert_eq!(
+            arguments,
+            BTreeMap::from([
+                (String::from("a"), Value::from("x y")),
+                (String::from("b"), Value::from("it\"s")),
+                (String::from("c"), Value::from("3")),
+            ])
+        );
+
+        assert_eq!(
+            parse_arguments("a=1 oops"),
+            Err(String::from(
+                "could not parse shortcode arguments at 'oops'"
+            ))
+        );
+    }
+
+    #[test]
+    fn standalone_and_paired() {
+        assert_eq!(
+            render("{% hr class=\"<fancy>\" %}\n\n{% quote source=\"Barth\" %}\n\n*Hi.*\n\n{% endquote %}\n"),
+            Ok(String::from(
+                "<hr class=\"&lt;fancy&gt;\">\
+                 <figure><blockquote>\n<p><em>Hi.</em></p>\n</blockquote><figcaption>Barth</figcaption></figure>"
+            ))
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(
+            render("{% box %}\n\n{% quote source=a %}\n\nHi.\n\n{% endquote %}\n\n{% endbox %}\n"),
+            Ok(String::from(
+                "<div class=\"box\"><figure><blockquote>\n<p>Hi.</p>\n</blockquote><figcaption>a</figcaption></figure></div>"
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_shortcodes_are_left_alone() {
+        let src = "{% include \"nav.html\" %}\n\n```\n{% quote %}\n```\n";
+        let mut blocks = Blocks::default();
+        assert_eq!(
+            extract(src, &shortcodes(), 1, &mut blocks),
+            Ok(Cow::Borrowed(src))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            render("Text.\n\n{% quote %}\n\nHi.\n"),
+            Err(String::from(
+                "line 3: '{% quote %}' is never closed with '{% endquote %}'"
+            ))
+        );
+        assert_eq!(
+            render("{% endbox %}\n"),
+            Err(String::from(
+                "line 1: '{% endbox %}' has no opening '{% box %}'"
+            ))
+        );
+        assert_eq!(
+            render("{% box %}\n\n{% quote %}\n\n{% endbox %}\n"),
+            Err(String::from(
+                "line 5: '{% endbox %}' does not close '{% quote %}' from line 3"
+            ))
+        );
+        assert_eq!(
+            render("{% hr class=\"a\" extra %}\n"),
+            Err(String::from(
+                "line 1: could not parse shortcode arguments at 'extra'"
+            ))
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Summaries for pages which don't supply their own: everything above a
+//! `<!-- more -->` marker if there is one, or else the first paragraph.
+//!
+//! Also counts the words in the page, for estimating reading time.
+
+use pulldown_cmark::{html, Event, Parser, Tag};
+
+/// The words in a page an average reader gets through each minute.
+pub(crate) const WORDS_PER_MINUTE: usize = 250;
+
+/// A page's summary, as HTML for layouts and feeds and as plain text for
+/// places which can't take markup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Summary {
+    pub(crate) html: String,
+    pub(crate) text: String,
+}
+
+impl Summary {
+    /// Render a summary supplied in a page's header, which may use Markdown.
+    pub(crate) fn from_markdown(markdown: &str) -> Summary {
+        Summary::from_events(&Parser::new(markdown).collect::<Vec<_>>())
+    }
+
+    fn from_events(events: &[Event]) -> Summary {
+        let mut html = String::new();
+        html::push_html(&mut html, events.iter().cloned());
+        Summary {
+            html,
+            text: text(events),
+        }
+    }
+}
+
+/// The summary of a page from its events, if it has any text to summarize.
+pub(super) fn extract(events: &[Event]) -> Option<Summary> {
+    let summary = match events.iter().position(is_more_marker) {
+        Some(marker) => close_tags(&events[..marker]),
+        None => first_paragraph(events)?.to_vec(),
+    };
+
+    // Footnotes are left behind: their definitions aren't in the summary.
+    let mut depth = 0;
+    let summary = summary
+        .into_iter()
+        .filter(|event| match event {
+            Event::Start(Tag::FootnoteDefinition(..)) => {
+                depth += 1;
+                false
+            }
+            Event::End(Tag::FootnoteDefinition(..)) => {
+                depth -= 1;
+                false
+            }
+            Event::FootnoteReference(..) => false,
+            _ => depth == 0,
+        })
+        .collect::<Vec<_>>();
+
+    let summary = Summary::from_events(&summary);
+    if summary.text.is_empty() {
+        None
+    } else {
+        Some(summary)
+    }
+}
+
+/// Count the words in a run of prose.
+pub(super) fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+fn is_more_marker(event: &Event) -> bool {
+    match event {
+        Event::Html(html) => {
+            let html = html.trim();
+            html.strip_prefix("<!--")
+                .and_then(|comment| comment.strip_suffix("-->"))
+                .is_some_and(|comment| comment.trim() == "more")
+        }
+        _ => false,
+    }
+}
+
+/// The events before a marker, with any tags still open there closed.
+fn close_tags<'a>(events: &[Event<'a>]) -> Vec<Event<'a>> {
+    let mut open = Vec::new();
+    for event in events {
+        match event {
+            Event::Start(tag) => open.push(tag.clone()),
+            Event::End(_) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+
+    let mut closed = events.to_vec();
+    closed.extend(open.into_iter().rev().map(Event::End));
+    closed
+}
+
+/// The first paragraph which isn't nested in anything else, like a list or
+/// a block quote; or failing that, the first paragraph at all.
+fn first_paragraph<'e, 'a>(events: &'e [Event<'a>]) -> Option<&'e [Event<'a>]> {
+    let mut depth = 0;
+    let mut top_level = None;
+    let mut any = None;
+    for (index, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::Paragraph) => {
+                if depth == 0 && top_level.is_none() {
+                    top_level = Some(index);
+                }
+                if any.is_none() {
+                    any = Some(index);
+                }
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    let start = top_level.or(any)?;
+    let end = events[start..]
+        .iter()
+        .position(|event| matches!(event, Event::End(Tag::Paragraph)))
+        .map_or(events.len(), |end| start + end + 1);
+    Some(&events[start..end])
+}
+
+/// The plain text of some events, with paragraphs separated by blank lines.
+fn text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Text(content) | Event::Code(content) => text.push_str(content),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::End(tag) if !is_inline(tag) && !text.is_empty() && !text.ends_with("\n\n") => {
+                text.push_str("\n\n");
+            }
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::Options;
+
+    #[test]
+    fn more_marker() {
+        let events =
+            Parser::new("Intro with *emphasis*.\n\nMore intro.\n\n<!-- more -->\n\nThe rest.")
+                .collect::<Vec<_>>();
+        let summary = extract(&events).expect("there is a summary");
+        assert_eq!(
+            summary.html,
+            "<p>Intro with <em>emphasis</em>.</p>\n<p>More intro.</p>\n"
+        );
+        assert_eq!(summary.text, "Intro with emphasis.\n\nMore intro.");
+    }
+
+    #[test]
+    fn first_paragraph() {
+        let events = Parser::new_ext(
+            "> A quote.\n\nThe point[^1].\n\nThe rest.\n\n[^1]: A note.",
+            Options::ENABLE_FOOTNOTES,
+        )
+        .collect::<Vec<_>>();
+        let summary = extract(&events).expect("there is a summary");
+        assert_eq!(summary.html, "<p>The point.</p>\n");
+        assert_eq!(summary.text, "The point.");
+    }
+}
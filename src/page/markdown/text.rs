@@ -0,0 +1,324 @@
+//! Render a page as plain text, for feeds' `content_text` and for search:
+//! readable without any markup, but losing as little as possible. Links keep
+//! their URLs, as `text (url)`; footnotes are numbered and collected at the
+//! end; code is left exactly as written.
+//!
+//! This walks the events after the main rendering pass, so by now code blocks
+//! and math are already HTML. Highlighted code is recovered by stripping the
+//! highlighter's markup, and math by the TeX KaTeX keeps in its annotations.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+
+use super::headings::EXPLICIT_ID;
+
+lazy_static! {
+    static ref TEX: Regex =
+        Regex::new(r#"(?s)<annotation encoding="application/x-tex">(?P<tex>.*?)</annotation>"#)
+            .expect("TeX annotation regex is legit");
+}
+
+pub(super) fn render(events: &[Event]) -> String {
+    let mut writer = Writer::default();
+    for event in events {
+        writer.write(event);
+    }
+    writer.finish()
+}
+
+#[derive(Default)]
+struct Writer {
+    /// The text so far, and the text of any footnote definitions in progress.
+    outputs: Vec<String>,
+    /// Footnote numbers, in order of first reference.
+    numbers: HashMap<String, usize>,
+    definitions: HashMap<String, String>,
+    /// The next number of each list we're in, or `None` for bulleted lists.
+    lists: Vec<Option<u64>>,
+    /// The destinations of the links we're in, and where their text starts.
+    links: Vec<(String, usize)>,
+    /// Where the text of the heading we're in starts.
+    heading: Option<usize>,
+    in_code: bool,
+}
+
+impl Writer {
+    fn output(&mut self) -> &mut String {
+        if self.outputs.is_empty() {
+            self.outputs.push(String::new());
+        }
+        self.outputs.last_mut().expect("there is always an output")
+    }
+
+    fn write(&mut self, event: &Event) {
+        match event {
+            Event::Text(text) | Event::Code(text) => self.output().push_str(text),
+            Event::Html(html) => self.html(html),
+            Event::SoftBreak => self.output().push(' '),
+            Event::HardBreak => self.output().push('\n'),
+            Event::Rule => self.end_block(),
+            Event::TaskListMarker(done) => {
+                self.output().push_str(if *done { "[x] " } else { "[ ] " })
+            }
+            Event::FootnoteReference(label) => {
+                let number = self.number(label);
+                self.output().push_str(&format!("[{}]", number));
+            }
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+        }
+    }
+
+    fn start(&mut self, tag: &Tag) {
+        match tag {
+            Tag::List(first) => {
+                self.end_line();
+                self.lists.push(*first);
+            }
+            Tag::Item => {
+                self.end_line();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => String::from("- "),
+                };
+                self.output().push_str(&(indent + &marker));
+            }
+            Tag::Link(_, dest, _) => {
+                let start = self.output().len();
+                self.links.push((dest.to_string(), start));
+            }
+            Tag::Heading(..) => self.heading = Some(self.output().len()),
+            Tag::FootnoteDefinition(_) => self.outputs.push(String::new()),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: &Tag) {
+        match tag {
+            Tag::Heading(..) => {
+                // The headings pass hasn't taken out explicit IDs yet.
+                if let Some(start) = self.heading.take() {
+                    let output = self.output();
+                    let id_start = EXPLICIT_ID
+                        .find(&output[start..])
+                        .map(|id| start + id.start());
+                    if let Some(id_start) = id_start {
+                        output.truncate(id_start);
+                    }
+                }
+                self.end_block()
+            }
+            Tag::Paragraph | Tag::BlockQuote | Tag::CodeBlock(_) => self.end_block(),
+            Tag::Table(_) => self.end_block(),
+            Tag::TableHead | Tag::TableRow => {
+                let output = self.output();
+                let trimmed = output.trim_end_matches(" | ").len();
+                output.truncate(trimmed);
+                output.push('\n');
+            }
+            Tag::TableCell => self.output().push_str(" | "),
+            Tag::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+            }
+            Tag::Item => self.end_line(),
+            Tag::Link(..) => {
+                if let Some((dest, start)) = self.links.pop() {
+                    let output = self.output();
+                    let text = output.get(start..).unwrap_or_default().trim();
+                    let shown = text == dest || dest.strip_prefix("mailto:") == Some(text);
+                    if !dest.is_empty() && !dest.starts_with('#') && !shown {
+                        output.push_str(&format!(" ({})", dest));
+                    }
+                }
+            }
+            Tag::FootnoteDefinition(label) if self.outputs.len() > 1 => {
+                let definition = self.outputs.pop().unwrap_or_default();
+                self.definitions
+                    .insert(label.to_string(), definition.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn html(&mut self, html: &str) {
+        if self.in_code {
+            if let Some(end) = html.find("</code></pre>") {
+                let code = strip_tags(&html[..end]);
+                self.output().push_str(&code);
+                self.in_code = false;
+                self.end_block();
+            } else {
+                let code = strip_tags(html);
+                self.output().push_str(&code);
+            }
+        } else if html.starts_with("<pre><code") {
+            self.end_block();
+            self.in_code = true;
+        } else if TEX.is_match(html) {
+            let tex = TEX
+                .captures_iter(html)
+                .map(|captures| decode_entities(&captures["tex"]))
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.output().push_str(&tex);
+        } else {
+            // Comments, and tags on their own, leave nothing worth keeping.
+            let text = strip_tags(html);
+            if !text.trim().is_empty() {
+                self.output().push_str(&text);
+            }
+        }
+    }
+
+    fn number(&mut self, label: &str) -> usize {
+        let next = self.numbers.len() + 1;
+        *self.numbers.entry(label.to_string()).or_insert(next)
+    }
+
+    /// Start a new line, unless we're at the start of one.
+    fn end_line(&mut self) {
+        let output = self.output();
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+    }
+
+    /// Leave a blank line after the block just finished.
+    fn end_block(&mut self) {
+        let output = self.output();
+        let trimmed = output.trim_end_matches([' ', '\n']).len();
+        output.truncate(trimmed);
+        if !output.is_empty() {
+            output.push_str("\n\n");
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let mut text = self.output().trim().to_string();
+
+        let mut notes = self
+            .numbers
+            .iter()
+            .filter_map(|(label, number)| {
+                self.definitions
+                    .get(label)
+                    .map(|definition| (*number, definition))
+            })
+            .collect::<Vec<_>>();
+        notes.sort_unstable_by_key(|(number, _)| *number);
+        if !notes.is_empty() {
+            text.push('\n');
+            for (number, definition) in notes {
+                text.push_str(&format!("\n[{}] {}", number, definition));
+            }
+        }
+
+        text.push('\n');
+        text
+    }
+}
+
+/// The text of some HTML: everything outside tags, with entities decoded.
+pub(crate) fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+}
+
+/// Decode the entities which escaping HTML produces.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| (&rest[1..end], end));
+        let character = entity.and_then(|(name, _)| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => name
+                .strip_prefix("#x")
+                .or_else(|| name.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| name.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                decoded.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::{Options, Parser};
+
+    #[test]
+    fn links_lists_and_footnotes() {
+        let events = Parser::new_ext(
+            "# Title\n\nSee [the docs](https://example.com/docs) and <https://example.com>.[^note]\n\n- one\n- two\n\n[^note]: A *note*.\n",
+            Options::ENABLE_FOOTNOTES,
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(
+            render(&events),
+            "Title\n\nSee the docs (https://example.com/docs) and https://example.com.[1]\n\n- one\n- two\n\n[1] A note.\n"
+        );
+    }
+
+    #[test]
+    fn highlighted_code() {
+        let events = vec![
+            Event::Start(Tag::Paragraph),
+            Event::Text("Code:".into()),
+            Event::End(Tag::Paragraph),
+            Event::Html("<pre><code class='Rust'>".into()),
+            Event::Text("".into()),
+            Event::Html(
+                "<span class=\"kw\">if</span> a &lt; b &amp;&amp; c {\n    x();\n}\n</code></pre>"
+                    .into(),
+            ),
+        ];
+        assert_eq!(render(&events), "Code:\n\nif a < b && c {\n    x();\n}\n");
+    }
+}
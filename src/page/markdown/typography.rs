@@ -0,0 +1,302 @@
+//! A typographic "smartening" pass over the text of a document: curly quotes,
+//! dashes, ellipses, non-breaking spaces, and small caps. It only ever sees
+//! `Event::Text`, so code and raw HTML pass through untouched. Attribute spans
+//! like `{#some--id}` are left alone, as are quotes escaped in the source.
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{CowStr, Event};
+use regex::Regex;
+
+use crate::config::Typography;
+
+lazy_static! {
+    /// A number followed by a (space and a) unit of measure.
+    static ref UNITS: Regex = Regex::new(
+        r"(?P<number>\d) (?P<unit>(?:[kKMGT]?B|[kMG]?Hz|km/h|mph|kph|km|cm|mm|m|kg|mg|g|lbs?|oz|mi|ft|yd|ms|min|hrs?|px|pt|rem|em|°[CF])\b|%)"
+    )
+    .expect("units regex is legit");
+
+    /// Runs of two or more capitals (plus trailing digits), e.g. "HTML5".
+    static ref ABBREVIATION: Regex =
+        Regex::new(r"\b[A-Z]{2,}[0-9]*\b").expect("abbreviation regex is legit");
+
+    /// An attribute span such as an explicit heading id, e.g. `{#intro}`.
+    static ref ATTRIBUTES: Regex = Regex::new(r"\{#[^}\s]*\}").expect("attributes regex is legit");
+}
+
+/// Stand-ins for `\"` and `\'`, which the parser hands us as bare quotes. The
+/// smartener turns them back into straight quotes instead of curling them.
+/// (Math, block, and shortcode placeholders use the private-use characters
+/// before these.)
+pub(super) const ESCAPED_DOUBLE_QUOTE: char = '\u{E006}';
+pub(super) const ESCAPED_SINGLE_QUOTE: char = '\u{E007}';
+
+/// The stand-in for a quote mark that was backslash-escaped in the source.
+pub(super) fn escaped(quote: char) -> Option<char> {
+    match quote {
+        '"' => Some(ESCAPED_DOUBLE_QUOTE),
+        '\'' => Some(ESCAPED_SINGLE_QUOTE),
+        _ => None,
+    }
+}
+
+/// Runs the typography pass, tracking just enough state across text events to
+/// get quotes right when e.g. `"*emphasis*"` splits a quotation across several
+/// of them.
+pub(super) struct Smartener<'t> {
+    settings: &'t Typography,
+    /// The last character we saw in the current block, if any.
+    previous: Option<char>,
+}
+
+impl<'t> Smartener<'t> {
+    pub(super) fn new(settings: &'t Typography) -> Smartener<'t> {
+        Smartener {
+            settings,
+            previous: None,
+        }
+    }
+
+    /// Forget the preceding text, e.g. because we started a new paragraph.
+    pub(super) fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Note non-text content (e.g. inline code) which the next quote mark
+    /// should treat as preceding text.
+    pub(super) fn saw(&mut self, content: &str) {
+        if let Some(last) = content.chars().last() {
+            self.previous = Some(last);
+        }
+    }
+
+    /// Smarten a run of text, returning the events to emit in its place. The
+    /// result is a single `Event::Text` unless small caps are enabled and
+    /// `allow_markup` is set, in which case abbreviations get wrapped.
+    pub(super) fn smarten<'e>(&mut self, text: &str, allow_markup: bool) -> Vec<Event<'e>> {
+        let mut events = Vec::new();
+        let mut last_end = 0;
+        for attributes in ATTRIBUTES.find_iter(text) {
+            if attributes.start() > last_end {
+                let before = &text[last_end..attributes.start()];
+                events.extend(self.smarten_run(before, allow_markup));
+            }
+
+            events.push(Event::Text(attributes.as_str().to_string().into()));
+            self.saw(attributes.as_str());
+            last_end = attributes.end();
+        }
+
+        if last_end < text.len() {
+            events.extend(self.smarten_run(&text[last_end..], allow_markup));
+        }
+
+        events
+    }
+
+    /// Smarten a run of text which holds no attribute spans.
+    fn smarten_run<'e>(&mut self, text: &str, allow_markup: bool) -> Vec<Event<'e>> {
+        let mut smart = self.punctuate(text);
+
+        if self.settings.units {
+            smart = UNITS.replace_all(&smart, "$number\u{a0}$unit").into_owned();
+        }
+
+        if self.settings.short_words > 0 {
+            smart = bind_short_words(&smart, self.settings.short_words);
+        }
+
+        if !(self.settings.small_caps && allow_markup) {
+            return vec![Event::Text(smart.into())];
+        }
+
+        let mut events = Vec::new();
+        let mut last_end = 0;
+        for abbreviation in ABBREVIATION.find_iter(&smart) {
+            if abbreviation.start() > last_end {
+                let before = &smart[last_end..abbreviation.start()];
+                events.push(Event::Text(CowStr::from(before.to_string())));
+            }
+
+            events.push(Event::Html(
+                format!("<abbr class=\"{}\">", self.settings.small_caps_class).into(),
+            ));
+            events.push(Event::Text(abbreviation.as_str().to_string().into()));
+            events.push(Event::Html("</abbr>".into()));
+            last_end = abbreviation.end();
+        }
+
+        if last_end < smart.len() {
+            events.push(Event::Text(smart[last_end..].to_string().into()));
+        }
+
+        events
+    }
+
+    /// Handle the character-level substitutions: quotes, dashes, and ellipses.
+    fn punctuate(&mut self, text: &str) -> String {
+        let settings = self.settings;
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut out = String::with_capacity(text.len());
+
+        let mut index = 0;
+        while index < chars.len() {
+            let c = chars[index];
+            let next = chars.get(index + 1).copied();
+            let after_next = chars.get(index + 2).copied();
+
+            let (replacement, consumed) = match c {
+                '"' if settings.quotes => {
+                    if opens(self.previous) {
+                        ('“', 1)
+                    } else {
+                        ('”', 1)
+                    }
+                }
+                '\'' if settings.quotes => {
+                    // Elisions at the start of a word, e.g. '90s, take an
+                    // apostrophe rather than an opening quote.
+                    let elides_number = next.is_some_and(|n| n.is_ascii_digit());
+                    if opens(self.previous) && !elides_number {
+                        ('‘', 1)
+                    } else {
+                        ('’', 1)
+                    }
+                }
+                '-' if settings.dashes && next == Some('-') => {
+                    if after_next == Some('-') {
+                        ('—', 3)
+                    } else {
+                        ('–', 2)
+                    }
+                }
+                '.' if settings.ellipses && next == Some('.') && after_next == Some('.') => {
+                    ('…', 3)
+                }
+                ESCAPED_DOUBLE_QUOTE => ('"', 1),
+                ESCAPED_SINGLE_QUOTE => ('\'', 1),
+                other => (other, 1),
+            };
+
+            out.push(replacement);
+            self.previous = Some(replacement);
+            index += consumed;
+        }
+
+        out
+    }
+}
+
+/// Whether a quote mark following `previous` opens a quotation (rather than
+/// closing one or being an apostrophe).
+fn opens(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{<‘“—–-/".contains(c),
+    }
+}
+
+/// Replace the space after any word of at most `max_len` letters with a
+/// non-breaking space.
+fn bind_short_words(text: &str, max_len: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    // The number of letters in the current word, or `None` if it contains
+    // anything other than letters and so does not count as a word at all.
+    let mut word_len = Some(0);
+
+    for c in text.chars() {
+        if c == ' ' {
+            match word_len {
+                Some(len) if len > 0 && len <= max_len => out.push('\u{a0}'),
+                _ => out.push(c),
+            }
+        } else {
+            out.push(c);
+        }
+
+        word_len = if c.is_whitespace() {
+            Some(0)
+        } else if c.is_alphabetic() {
+            word_len.map(|len| len + 1)
+        } else {
+            None
+        };
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smarten(text: &str, settings: &Typography) -> String {
+        Smartener::new(settings)
+            .smarten(text, true)
+            .into_iter()
+            .map(|event| match event {
+                Event::Text(text) | Event::Html(text) => text.to_string(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn punctuation() {
+        let settings = Typography::default();
+        assert_eq!(
+            smarten(
+                r#""It's the '90s," she said -- or---maybe not..."#,
+                &settings
+            ),
+            "“It’s the ’90s,” she said – or—maybe not…"
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_stay_straight() {
+        let settings = Typography::default();
+        let text = format!("a {}b{} 'c'", ESCAPED_DOUBLE_QUOTE, ESCAPED_DOUBLE_QUOTE);
+        assert_eq!(smarten(&text, &settings), "a \"b\" ‘c’");
+
+        let text = format!("{}s", ESCAPED_SINGLE_QUOTE);
+        assert_eq!(smarten(&text, &settings), "'s");
+    }
+
+    #[test]
+    fn attributes_are_left_alone() {
+        let settings = Typography {
+            small_caps: true,
+            ..Typography::default()
+        };
+        assert_eq!(
+            smarten("Step -- one {#step--ONE}", &settings),
+            "Step – one {#step--ONE}"
+        );
+        assert_eq!(smarten("{#a--b} \"x\"", &settings), "{#a--b} “x”");
+    }
+
+    #[test]
+    fn spacing() {
+        let settings = Typography {
+            short_words: 2,
+            ..Typography::default()
+        };
+        assert_eq!(
+            smarten("ran 10 km, then to a store", &settings),
+            "ran 10\u{a0}km, then to\u{a0}a\u{a0}store"
+        );
+    }
+
+    #[test]
+    fn small_caps() {
+        let settings = Typography {
+            small_caps: true,
+            ..Typography::default()
+        };
+        assert_eq!(
+            smarten("I like HTML5.", &settings),
+            "I like <abbr class=\"small-caps\">HTML5</abbr>."
+        );
+    }
+}
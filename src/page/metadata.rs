@@ -1,10 +1,17 @@
+mod cascade;
+mod photo;
 mod serial;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, FixedOffset};
 use serial::{Book, Qualifiers, Series, Subscribe};
 
+use crate::config::{Photos, TypographyOverrides};
+use crate::history::Dates;
+
+pub(crate) use photo::Photo;
+
 #[derive(Debug)]
 pub(crate) enum RequiredFields {
     Title(String),
@@ -25,30 +32,114 @@ pub(crate) struct Metadata {
     /// The path to this piece of content.
     pub(crate) slug: String,
 
+    // Always `base.html` until there are layouts to choose from.
+    #[allow(dead_code)]
     layout: String,
 
-    subtitle: Option<String>,
-    summary: Option<String>,
+    pub(crate) subtitle: Option<String>,
+    pub(crate) summary: Option<String>,
+    // These are parsed so that headers stay valid, but only layouts will
+    // show them.
+    #[allow(dead_code)]
     qualifiers: Option<Qualifiers>,
-    updated: Option<DateTime<FixedOffset>>,
+    pub(crate) updated: Option<DateTime<FixedOffset>>,
+
+    /// When the page was first committed, if the site dates pages by their
+    /// history. Unlike `date`, this says nothing about when it was published.
+    pub(crate) created: Option<DateTime<FixedOffset>>,
+
+    #[allow(dead_code)]
     thanks: Option<String>,
-    tags: Vec<String>,
+    pub(crate) tags: Vec<String>,
+    #[allow(dead_code)]
     featured: bool,
+    #[allow(dead_code)]
     book: Option<Book>,
+    #[allow(dead_code)]
     series: Option<Series>,
+    #[allow(dead_code)]
     subscribe: Option<Subscribe>,
+
+    /// Page-specific tweaks to the site's typography settings.
+    pub(crate) typography: Option<TypographyOverrides>,
+
+    /// A page-specific bibliography for citations, overriding the site's.
+    pub(crate) bibliography: Option<PathBuf>,
+
+    /// Whether to generate a table of contents for the page.
+    pub(crate) toc: bool,
+
+    /// Whether to render footnotes as sidenotes, overriding the site's setting.
+    pub(crate) sidenotes: Option<bool>,
+
+    /// The photo the page is about, with its EXIF data.
+    pub(crate) photo: Option<Photo>,
+
+    /// Old URLs of the page, which should redirect to it.
+    pub(crate) aliases: Vec<String>,
+
+    /// Whether to list the page in the sitemap.
+    pub(crate) sitemap: bool,
+
+    /// The image to show when the page is shared, as a path to an asset or a
+    /// full URL.
+    pub(crate) image: Option<String>,
+
+    /// Whether to include the page in the search index.
+    pub(crate) search: bool,
+
+    /// Whether the page is unfinished, and so left out of the site.
+    pub(crate) draft: bool,
+
+    /// Problems with the header which don't stop the page from building.
+    pub(crate) warnings: Vec<String>,
 }
 
 impl Metadata {
     pub(super) fn new(
-        src_path: &PathBuf,
-        root_dir: &PathBuf,
+        src_path: &Path,
+        root_dir: &Path,
         header: &str,
+        photos: &Photos,
+        history: Option<Dates>,
     ) -> Result<Metadata, String> {
         let item_metadata: serial::Metadata =
             serde_yaml::from_str(header).map_err(|e| format!("{}", e))?;
 
-        let required = (match (item_metadata.title, item_metadata.date) {
+        let photo = Photo::load(item_metadata.photo.as_ref(), src_path, root_dir, photos.gps)?;
+
+        // A photo's page is dated when it was taken, unless it says otherwise.
+        // Its history doesn't date it: a page's date is when it was published,
+        // and a commit can come long before that.
+        let date = item_metadata
+            .date
+            .or_else(|| photo.as_ref().and_then(|photo| photo.exif.captured));
+        let created = history.map(|history| history.created);
+
+        // A page was updated when it last changed, if that was after the day
+        // it's dated (or, failing that, created).
+        let mut warnings = Vec::new();
+        let updated = match (item_metadata.updated, history) {
+            (Some(updated), Some(history)) => {
+                let changed = history.updated.with_timezone(&updated.timezone());
+                if changed.date() > updated.date() {
+                    warnings.push(format!(
+                        "updated {}, but last changed {}",
+                        updated.format("%Y-%m-%d"),
+                        changed.format("%Y-%m-%d")
+                    ));
+                }
+                Some(updated)
+            }
+            (Some(updated), None) => Some(updated),
+            (None, Some(history)) => Some(history.updated).filter(|changed| {
+                date.or(created)
+                    .is_none_or(|date| changed.with_timezone(&date.timezone()).date() > date.date())
+            }),
+            (None, None) => None,
+        };
+
+        let required = (match (item_metadata.title, date) {
             (Some(title), Some(date)) => Ok(RequiredFields::Both { title, date }),
             (None, Some(date)) => Ok(RequiredFields::Date(date)),
             (Some(title), None) => Ok(RequiredFields::Title(title)),
@@ -97,10 +188,19 @@ impl Metadata {
                         )
                     })
                     .to_string_lossy()
-                    .to_owned()
                     .to_string()
             });
 
+        let mut aliases = cascade::aliases(src_path, root_dir)?;
+        aliases.extend(item_metadata.aliases);
+
+        // Like Pandoc, resolve the bibliography relative to the document.
+        let bibliography = item_metadata.bibliography.map(|bibliography| {
+            src_path
+                .parent()
+                .map_or_else(|| bibliography.clone(), |dir| dir.join(&bibliography))
+        });
+
         Ok(Metadata {
             required,
             slug,
@@ -108,13 +208,45 @@ impl Metadata {
             layout: String::from("base.html"), // TODO: not this!
             summary: item_metadata.summary,
             qualifiers: item_metadata.qualifiers,
-            updated: item_metadata.updated,
+            updated,
+            created,
             thanks: item_metadata.thanks,
             tags: item_metadata.tags,
             featured: item_metadata.featured,
             book: item_metadata.book,
             series: item_metadata.series,
             subscribe: item_metadata.subscribe,
+            typography: item_metadata.typography,
+            bibliography,
+            toc: item_metadata.toc,
+            sidenotes: item_metadata.sidenotes,
+            photo,
+            aliases,
+            sitemap: item_metadata.sitemap.unwrap_or(true),
+            image: item_metadata.image,
+            search: item_metadata.search.unwrap_or(true),
+            draft: item_metadata.draft,
+            warnings,
         })
     }
+
+    pub(crate) fn title(&self) -> Option<&str> {
+        match &self.required {
+            RequiredFields::Title(title) | RequiredFields::Both { title, .. } => Some(title),
+            RequiredFields::Date(_) => None,
+        }
+    }
+
+    pub(crate) fn date(&self) -> Option<&DateTime<FixedOffset>> {
+        match &self.required {
+            RequiredFields::Date(date) | RequiredFields::Both { date, .. } => Some(date),
+            RequiredFields::Title(_) => None,
+        }
+    }
+
+    /// Whether the page is out `as_of` the given time: it isn't a draft, and
+    /// isn't dated any later.
+    pub(crate) fn is_published(&self, as_of: &DateTime<FixedOffset>) -> bool {
+        !self.draft && self.date().is_none_or(|date| date <= as_of)
+    }
 }
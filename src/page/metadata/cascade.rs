@@ -0,0 +1,119 @@
+//! Data which applies to every page in a directory, from 11ty-style directory
+//! data files: `journal/journal.11tydata.json` applies to everything under
+//! `journal/`. Values can use the page's `page.fileSlug`, with a `slug`
+//! filter, as in `"/blog/{{ page.fileSlug | slug }}/"`.
+//!
+//! Only `aliases` cascade so far. Like 11ty, lists from every directory are
+//! merged, outermost first, ahead of the page's own.
+
+use std::path::Path;
+
+use minijinja::{context, Environment};
+use serde_derive::Deserialize;
+
+#[derive(Deserialize, Debug, Default)]
+struct DirectoryData {
+    #[serde(default)]
+    aliases: Vec<String>,
+}
+
+/// The aliases every directory between `root_dir` and the page at `src_path`
+/// gives it.
+pub(super) fn aliases(src_path: &Path, root_dir: &Path) -> Result<Vec<String>, String> {
+    let dir = match src_path.parent() {
+        Some(dir) if dir.starts_with(root_dir) => dir,
+        _ => return Ok(Vec::new()),
+    };
+
+    let mut dirs = dir
+        .ancestors()
+        .take_while(|ancestor| ancestor.starts_with(root_dir))
+        .collect::<Vec<_>>();
+    dirs.reverse();
+
+    let mut templates = Vec::new();
+    for dir in dirs {
+        let name = match dir.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => continue,
+        };
+        let path = dir.join(format!("{}.11tydata.json", name));
+        if !path.is_file() {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+        let data: DirectoryData = serde_json::from_str(&contents)
+            .map_err(|e| format!("could not parse '{}': {}", path.display(), e))?;
+        templates.extend(data.aliases);
+    }
+    if templates.is_empty() {
+        return Ok(templates);
+    }
+
+    let mut env = Environment::new();
+    env.add_filter("slug", |value: String| slug::slugify(value));
+    let file_slug = src_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let page = context! { fileSlug => file_slug };
+    templates
+        .iter()
+        .map(|template| {
+            env.render_str(template, context! { page => &page })
+                .map_err(|e| format!("could not render alias '{}': {}", template, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A content directory with data for itself and for `journal/`, somewhere
+    /// of its own.
+    fn content() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lx-cascade-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let content = dir.join("content");
+        std::fs::create_dir_all(content.join("journal/2020")).expect("can create");
+        for (path, data) in [
+            (
+                "content.11tydata.json",
+                r#"{ "aliases": ["/all/{{ page.fileSlug }}"] }"#,
+            ),
+            (
+                "journal/journal.11tydata.json",
+                r#"{ "aliases": ["/blog/{{ page.fileSlug | slug }}/"], "layout": "post" }"#,
+            ),
+            // Only data named for its directory counts.
+            (
+                "journal/2020/other.11tydata.json",
+                r#"{ "aliases": ["/nope"] }"#,
+            ),
+        ] {
+            std::fs::write(content.join(path), data).expect("can write");
+        }
+        content
+    }
+
+    #[test]
+    fn outermost_directories_come_first() {
+        let content = content();
+        assert_eq!(
+            aliases(&content.join("journal/2020/My Post.md"), &content),
+            Ok(vec![
+                String::from("/all/My Post"),
+                String::from("/blog/my-post/"),
+            ])
+        );
+        assert_eq!(
+            aliases(&content.join("About.md"), &content),
+            Ok(vec![String::from("/all/About")])
+        );
+        std::fs::remove_dir_all(content.parent().expect("has a parent")).ok();
+    }
+}
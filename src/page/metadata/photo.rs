@@ -0,0 +1,349 @@
+//! Photos backing a page: the EXIF data recorded by the camera, to show
+//! alongside the image, and to date the page when its header doesn't.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use exif::{Exif as RawExif, In, Tag, Value};
+use serde_derive::{Deserialize, Serialize};
+
+/// The photo as given in a page's header: just a path, or a path with
+/// page-specific settings.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(super) enum PhotoSource {
+    Path(PathBuf),
+    Detailed { path: PathBuf, gps: Option<bool> },
+}
+
+impl PhotoSource {
+    fn path(&self) -> &Path {
+        match self {
+            PhotoSource::Path(path) | PhotoSource::Detailed { path, .. } => path,
+        }
+    }
+
+    fn gps(&self) -> Option<bool> {
+        match self {
+            PhotoSource::Path(_) => None,
+            PhotoSource::Detailed { gps, .. } => *gps,
+        }
+    }
+}
+
+/// The formats which can carry EXIF data, for finding the photo backing a
+/// page from its file name.
+const EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "JPG", "JPEG", "tif", "tiff"];
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Photo {
+    /// Where the photo is on disk.
+    pub(crate) path: PathBuf,
+    pub(crate) exif: Exif,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct Exif {
+    /// The make and model of the camera, e.g. "FUJIFILM X-T3".
+    pub(crate) camera: Option<String>,
+    pub(crate) lens: Option<String>,
+    pub(crate) exposure: Exposure,
+    pub(crate) captured: Option<DateTime<FixedOffset>>,
+    pub(crate) gps: Option<Gps>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub(crate) struct Exposure {
+    /// The shutter speed as photographers write it, e.g. "1/250" or "2".
+    pub(crate) shutter: Option<String>,
+    /// The f-number, e.g. `2.8` for f/2.8.
+    pub(crate) aperture: Option<f64>,
+    pub(crate) iso: Option<u32>,
+    /// In millimetres.
+    pub(crate) focal_length: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct Gps {
+    /// In decimal degrees, north positive.
+    pub(crate) latitude: f64,
+    /// In decimal degrees, east positive.
+    pub(crate) longitude: f64,
+    /// In metres above sea level.
+    pub(crate) altitude: Option<f64>,
+}
+
+impl Photo {
+    /// Find and read the photo for the page at `src_path`: the one named in its
+    /// header, if any, or otherwise an image next to it with the same name.
+    /// Paths in headers are relative to the page, or to the site's `_static`
+    /// directory if they start with `/`. `gps` is the site's setting for
+    /// keeping locations, which the header can override.
+    pub(super) fn load(
+        source: Option<&PhotoSource>,
+        src_path: &Path,
+        root_dir: &Path,
+        gps: bool,
+    ) -> Result<Option<Photo>, String> {
+        let (path, gps) = match source {
+            Some(source) => {
+                let path = source.path();
+                let path = match path.strip_prefix("/") {
+                    Ok(from_root) => root_dir
+                        .parent()
+                        .unwrap_or(root_dir)
+                        .join("_static")
+                        .join(from_root),
+                    Err(_) => src_path.parent().unwrap_or(root_dir).join(path),
+                };
+                (path, source.gps().unwrap_or(gps))
+            }
+            None => match EXTENSIONS
+                .iter()
+                .map(|extension| src_path.with_extension(extension))
+                .find(|path| path.is_file())
+            {
+                Some(path) => (path, gps),
+                None => return Ok(None),
+            },
+        };
+
+        let file = File::open(&path)
+            .map_err(|e| format!("could not open photo '{}': {}", path.display(), e))?;
+        let mut exif = match exif::Reader::new().read_from_container(&mut BufReader::new(file)) {
+            Ok(raw) => Exif::from(&raw),
+            // Plenty of images have no EXIF data at all, and that's fine.
+            Err(exif::Error::NotFound(_)) => Exif::default(),
+            Err(e) => {
+                return Err(format!(
+                    "could not read EXIF data from '{}': {}",
+                    path.display(),
+                    e
+                ))
+            }
+        };
+        if !gps {
+            exif.gps = None;
+        }
+
+        Ok(Some(Photo { path, exif }))
+    }
+}
+
+impl From<&RawExif> for Exif {
+    fn from(raw: &RawExif) -> Exif {
+        let field = |tag| raw.get_field(tag, In::PRIMARY).map(|field| &field.value);
+        let ascii = |tag| match field(tag)? {
+            Value::Ascii(values) => values
+                .first()
+                .map(|value| String::from_utf8_lossy(value).trim().to_string())
+                .filter(|value| !value.is_empty()),
+            _ => None,
+        };
+        let rational = |tag, index: usize| match field(tag)? {
+            Value::Rational(values) => values
+                .get(index)
+                .filter(|value| value.denom != 0)
+                .map(|value| value.to_f64()),
+            _ => None,
+        };
+
+        let camera = match (ascii(Tag::Make), ascii(Tag::Model)) {
+            // Most cameras repeat the make in the model.
+            (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+            (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+            (make, model) => make.or(model),
+        };
+
+        let shutter = match field(Tag::ExposureTime) {
+            Some(Value::Rational(values)) => values
+                .first()
+                .filter(|value| value.num != 0 && value.denom != 0)
+                .map(|value| {
+                    if value.num >= value.denom {
+                        format!("{}", value.to_f64())
+                    } else {
+                        format!("1/{}", (1.0 / value.to_f64()).round())
+                    }
+                }),
+            _ => None,
+        };
+
+        let captured = ascii(Tag::DateTimeOriginal)
+            .or_else(|| ascii(Tag::DateTime))
+            .and_then(|value| exif::DateTime::from_ascii(value.as_bytes()).ok())
+            .and_then(|mut date_time| {
+                if let Some(offset) = ascii(Tag::OffsetTimeOriginal) {
+                    // A malformed offset is as good as none.
+                    let _ = date_time.parse_offset(offset.as_bytes());
+                }
+                to_chrono(&date_time)
+            });
+
+        let coordinate = |tag, reference, negative: &str| {
+            let degrees = rational(tag, 0)? + rational(tag, 1)? / 60.0 + rational(tag, 2)? / 3600.0;
+            Some(if ascii(reference).as_deref() == Some(negative) {
+                -degrees
+            } else {
+                degrees
+            })
+        };
+        let gps = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S").and_then(|latitude| {
+            let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?;
+            let altitude = rational(Tag::GPSAltitude, 0).map(|altitude| {
+                // A reference of 1 means below sea level.
+                match field(Tag::GPSAltitudeRef).and_then(|value| value.get_uint(0)) {
+                    Some(1) => -altitude,
+                    _ => altitude,
+                }
+            });
+            Some(Gps {
+                latitude,
+                longitude,
+                altitude,
+            })
+        });
+
+        Exif {
+            camera,
+            lens: ascii(Tag::LensModel),
+            exposure: Exposure {
+                shutter,
+                aperture: rational(Tag::FNumber, 0),
+                iso: field(Tag::PhotographicSensitivity).and_then(|value| value.get_uint(0)),
+                focal_length: rational(Tag::FocalLength, 0),
+            },
+            captured,
+            gps,
+        }
+    }
+}
+
+/// EXIF dates only have a time zone if the camera recorded one; otherwise, the
+/// best we can do is to assume UTC.
+fn to_chrono(date_time: &exif::DateTime) -> Option<DateTime<FixedOffset>> {
+    let offset = FixedOffset::east_opt(i32::from(date_time.offset.unwrap_or(0)) * 60)?;
+    let naive = NaiveDate::from_ymd_opt(
+        i32::from(date_time.year),
+        u32::from(date_time.month),
+        u32::from(date_time.day),
+    )?
+    .and_hms_nano_opt(
+        u32::from(date_time.hour),
+        u32::from(date_time.minute),
+        u32::from(date_time.second),
+        date_time.nanosecond.unwrap_or(0),
+    )?;
+    offset.from_local_datetime(&naive).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use exif::experimental::Writer;
+    use exif::{Field, Rational};
+
+    use super::*;
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        }
+    }
+
+    fn ascii(value: &str) -> Value {
+        Value::Ascii(vec![value.as_bytes().to_vec()])
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Value {
+        Value::Rational(values.iter().map(|&value| Rational::from(value)).collect())
+    }
+
+    /// What we make of EXIF data with just these fields.
+    fn parse(fields: &[Field]) -> Exif {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).expect("test EXIF is legit");
+        let raw = exif::Reader::new()
+            .read_raw(tiff.into_inner())
+            .expect("test EXIF reads back");
+        Exif::from(&raw)
+    }
+
+    #[test]
+    fn camera_exposure_and_date() {
+        let exif = parse(&[
+            field(Tag::Make, ascii("FUJIFILM")),
+            field(Tag::Model, ascii("FUJIFILM X-T3")),
+            field(Tag::LensModel, ascii("XF35mmF1.4 R")),
+            field(Tag::ExposureTime, rationals(&[(1, 250)])),
+            field(Tag::FNumber, rationals(&[(28, 10)])),
+            field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+            field(Tag::FocalLength, rationals(&[(35, 1)])),
+            field(Tag::DateTimeOriginal, ascii("2021:06:15 18:30:00")),
+            field(Tag::OffsetTimeOriginal, ascii("+02:00")),
+        ]);
+
+        assert_eq!(exif.camera.as_deref(), Some("FUJIFILM X-T3"));
+        assert_eq!(exif.lens.as_deref(), Some("XF35mmF1.4 R"));
+        assert_eq!(exif.exposure.shutter.as_deref(), Some("1/250"));
+        assert_eq!(exif.exposure.aperture, Some(2.8));
+        assert_eq!(exif.exposure.iso, Some(400));
+        assert_eq!(exif.exposure.focal_length, Some(35.0));
+        assert_eq!(
+            exif.captured.map(|captured| captured.to_rfc3339()),
+            Some(String::from("2021-06-15T18:30:00+02:00"))
+        );
+        assert!(exif.gps.is_none());
+
+        let exif = parse(&[
+            field(Tag::Make, ascii("Canon")),
+            field(Tag::Model, ascii("EOS R")),
+            field(Tag::ExposureTime, rationals(&[(2, 1)])),
+            field(Tag::DateTime, ascii("2020:01:02 03:04:05")),
+        ]);
+        assert_eq!(exif.camera.as_deref(), Some("Canon EOS R"));
+        assert_eq!(exif.exposure.shutter.as_deref(), Some("2"));
+        assert_eq!(
+            exif.captured.map(|captured| captured.to_rfc3339()),
+            Some(String::from("2020-01-02T03:04:05+00:00"))
+        );
+    }
+
+    #[test]
+    fn gps_signs_follow_their_references() {
+        let position = |latitude: &str, longitude: &str, altitude: Option<u8>| {
+            let mut fields = vec![
+                field(Tag::GPSLatitude, rationals(&[(41, 1), (24, 1), (36, 1)])),
+                field(Tag::GPSLatitudeRef, ascii(latitude)),
+                field(Tag::GPSLongitude, rationals(&[(2, 1), (9, 1), (0, 1)])),
+                field(Tag::GPSLongitudeRef, ascii(longitude)),
+                field(Tag::GPSAltitude, rationals(&[(12, 1)])),
+            ];
+            if let Some(reference) = altitude {
+                fields.push(field(Tag::GPSAltitudeRef, Value::Byte(vec![reference])));
+            }
+            let gps = parse(&fields).gps.expect("has a position");
+            (gps.latitude, gps.longitude, gps.altitude)
+        };
+
+        assert_eq!(position("N", "E", None), (41.41, 2.15, Some(12.0)));
+        assert_eq!(position("S", "W", Some(0)), (-41.41, -2.15, Some(12.0)));
+        assert_eq!(position("N", "W", Some(1)), (41.41, -2.15, Some(-12.0)));
+
+        // Half a position is no position at all.
+        let exif = parse(&[
+            field(Tag::GPSLatitude, rationals(&[(41, 1), (24, 1), (36, 1)])),
+            field(Tag::GPSLatitudeRef, ascii("N")),
+        ]);
+        assert!(exif.gps.is_none());
+    }
+}
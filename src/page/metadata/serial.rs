@@ -2,9 +2,15 @@
 //! and associated data from JSON/TOML/YAML/JSON5/whatever else I decide to
 //! support in data files.
 
+use std::path::PathBuf;
+
 use chrono::{DateTime, FixedOffset};
 use serde_derive::Deserialize;
 
+use crate::config::TypographyOverrides;
+
+use super::photo::PhotoSource;
+
 #[derive(Deserialize, Debug)]
 pub(super) struct Metadata {
     pub(super) title: Option<String>,
@@ -19,18 +25,38 @@ pub(super) struct Metadata {
     pub(super) tags: Vec<String>,
     #[serde(default)]
     pub(super) featured: bool,
+    // Accepted, but ignored until there are layouts to choose from.
+    #[allow(dead_code)]
     pub(super) layout: Option<String>,
     pub(super) book: Option<Book>,
     pub(super) series: Option<Series>,
     pub(super) subscribe: Option<Subscribe>,
+    pub(super) typography: Option<TypographyOverrides>,
+    pub(super) bibliography: Option<PathBuf>,
+    #[serde(default)]
+    pub(super) toc: bool,
+    pub(super) sidenotes: Option<bool>,
+    pub(super) photo: Option<PhotoSource>,
+    #[serde(default)]
+    pub(super) aliases: Vec<String>,
+    pub(super) sitemap: Option<bool>,
+    pub(super) search: Option<bool>,
+    #[serde(alias = "banner")]
+    pub(super) image: Option<String>,
+    #[serde(default)]
+    pub(super) draft: bool,
 }
 
+// This and the rest of the header's structures are only read by layouts,
+// which don't exist yet.
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(super) struct Qualifiers {
     audience: Option<String>,
     epistemic: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(super) struct Subscribe {
     atom: Option<String>,
@@ -41,6 +67,7 @@ pub(super) struct Subscribe {
 // things being handled by "the cascade" in 11ty, and this *cannot* handle that.
 // As with a bunch of other things, the input from disk should have more options
 // and then the final merged data fewer.
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(super) struct Book {
     title: Option<String>,
@@ -56,6 +83,7 @@ pub(super) struct Book {
     review: Option<Review>,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(super) struct Review {
     rating: Rating,
@@ -93,6 +121,7 @@ impl std::fmt::Display for Rating {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub(super) struct Series {
     // The name is optional: it could be supplied via the data file somewhere up
@@ -0,0 +1,252 @@
+//! What a page looks like when shared: the [Open Graph] and Twitter card
+//! metadata, and the canonical URL, in one place so every layout gets them
+//! the same way. The build writes `html` into every page's `<head>`; templates
+//! can use the fields directly.
+//!
+//! [Open Graph]: https://ogp.me
+
+use chrono::{DateTime, FixedOffset};
+use pulldown_cmark::escape::escape_html;
+use serde_derive::Serialize;
+
+use crate::config::Config;
+
+use super::markdown::summary::Summary;
+use super::markdown::text::strip_tags;
+use super::metadata::Metadata;
+
+/// The longest description worth giving: previews cut off around here.
+const DESCRIPTION_LENGTH: usize = 200;
+
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct Social {
+    pub(crate) title: String,
+    pub(crate) description: Option<String>,
+    /// The canonical URL of the page.
+    pub(crate) url: String,
+    /// The full URL of the page's preview image.
+    pub(crate) image: Option<String>,
+    pub(crate) site_name: String,
+    pub(crate) author: String,
+    /// The Twitter handle of the author, from the links in the site config.
+    pub(crate) twitter: Option<String>,
+    /// The Open Graph type: `article` for dated pages, `website` otherwise.
+    pub(crate) kind: &'static str,
+    pub(crate) published: Option<DateTime<FixedOffset>>,
+    pub(crate) modified: Option<DateTime<FixedOffset>>,
+}
+
+impl Social {
+    /// `image` is the full URL of the page's image, already resolved.
+    pub(crate) fn new(
+        metadata: &Metadata,
+        summary: Option<&Summary>,
+        url: String,
+        image: Option<String>,
+        config: &Config,
+    ) -> Social {
+        // Every page without a title has a date instead.
+        let title = match (metadata.title(), metadata.date()) {
+            (Some(title), _) => strip_tags(title.trim()),
+            (None, Some(date)) => date.format("%B %-d, %Y").to_string(),
+            (None, None) => config.title.normal.clone(),
+        };
+
+        let description = summary.map(|summary| truncate(&summary.text, DESCRIPTION_LENGTH));
+
+        let twitter = config.author.links.iter().find_map(|link| {
+            let handle = link
+                .trim_end_matches('/')
+                .strip_prefix("https://twitter.com/")
+                .or_else(|| link.trim_end_matches('/').strip_prefix("https://x.com/"))?;
+            Some(format!("@{}", handle))
+        });
+
+        let published = metadata.date().copied();
+        Social {
+            title,
+            description,
+            url,
+            image,
+            site_name: config.title.normal.clone(),
+            author: config.author.name.clone(),
+            twitter,
+            kind: if published.is_some() {
+                "article"
+            } else {
+                "website"
+            },
+            published,
+            modified: metadata.updated,
+        }
+    }
+
+    /// The `<link>` and `<meta>` tags for the page's `<head>`.
+    pub(crate) fn html(&self) -> String {
+        let mut html = format!("<link rel=\"canonical\" href=\"{}\">\n", escape(&self.url));
+        let mut meta = |attribute: &str, name: &str, value: &str| {
+            html.push_str(&format!(
+                "<meta {}=\"{}\" content=\"{}\">\n",
+                attribute,
+                name,
+                escape(value)
+            ));
+        };
+
+        meta("property", "og:type", self.kind);
+        meta("property", "og:title", &self.title);
+        meta("property", "og:url", &self.url);
+        meta("property", "og:site_name", &self.site_name);
+        if let Some(description) = &self.description {
+            meta("property", "og:description", description);
+            meta("name", "description", description);
+        }
+        if let Some(image) = &self.image {
+            meta("property", "og:image", image);
+        }
+        if self.kind == "article" {
+            meta("property", "article:author", &self.author);
+            if let Some(published) = &self.published {
+                meta(
+                    "property",
+                    "article:published_time",
+                    &published.to_rfc3339(),
+                );
+            }
+            if let Some(modified) = &self.modified {
+                meta("property", "article:modified_time", &modified.to_rfc3339());
+            }
+        }
+
+        let card = if self.image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        };
+        meta("name", "twitter:card", card);
+        if let Some(twitter) = &self.twitter {
+            meta("name", "twitter:creator", twitter);
+        }
+        html
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, value).expect("writing to a String cannot fail");
+    escaped
+}
+
+/// Cut `text` down to at most `length` characters, at a word boundary.
+fn truncate(text: &str, length: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= length {
+        return text;
+    }
+
+    let cut = text
+        .char_indices()
+        .nth(length)
+        .map_or(text.len(), |(index, _)| index);
+    let end = text[..cut].rfind(' ').unwrap_or(cut);
+    format!(
+        "{}…",
+        text[..end].trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::page::Page;
+
+    fn social(header: &str, summary: Option<&str>, config: &Config) -> Social {
+        let page = Page::for_tests("page.md", header, config);
+        let summary = summary.map(Summary::from_markdown);
+        Social::new(
+            &page.metadata,
+            summary.as_ref(),
+            String::from("https://example.com/page"),
+            None,
+            config,
+        )
+    }
+
+    #[test]
+    fn titles_fall_back_to_the_date_then_the_site() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        assert_eq!(
+            social("title: A <em>Fine</em> Page", None, &config).title,
+            "A Fine Page"
+        );
+        assert_eq!(
+            social("date: 2024-03-05T09:00:00-07:00", None, &config).title,
+            "March 5, 2024"
+        );
+    }
+
+    #[test]
+    fn dated_pages_are_articles() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        let article = social(
+            "title: Post\ndate: 2024-03-05T09:00:00-07:00",
+            None,
+            &config,
+        );
+        assert_eq!(article.kind, "article");
+        let html = article.html();
+        assert!(html.contains("<meta property=\"og:type\" content=\"article\">"));
+        assert!(html.contains(
+            "<meta property=\"article:published_time\" content=\"2024-03-05T09:00:00-07:00\">"
+        ));
+        assert!(html.contains("<meta property=\"article:author\" content=\"Jo Example\">"));
+
+        let page = social("title: About", None, &config);
+        assert_eq!(page.kind, "website");
+        assert!(!page.html().contains("article:"));
+    }
+
+    #[test]
+    fn descriptions_are_short_and_escaped() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        let social = social(
+            "title: Tom & Jerry",
+            Some("A \"cat\" and   a mouse."),
+            &config,
+        );
+        assert_eq!(
+            social.description.as_deref(),
+            Some("A \"cat\" and a mouse.")
+        );
+        let html = social.html();
+        assert!(html.contains("<meta property=\"og:title\" content=\"Tom &amp; Jerry\">"));
+        assert!(
+            html.contains("<meta name=\"description\" content=\"A &quot;cat&quot; and a mouse.\">")
+        );
+        assert!(html.contains("<meta name=\"twitter:card\" content=\"summary\">"));
+        assert!(!html.contains("twitter:creator"));
+    }
+
+    #[test]
+    fn truncates_at_word_boundaries() {
+        assert_eq!(truncate("short enough", 20), "short enough");
+        assert_eq!(truncate("one two, three four", 12), "one two…");
+        assert_eq!(truncate("unbrokenword", 5), "unbro…");
+    }
+
+    #[test]
+    fn twitter_handles_come_from_author_links() {
+        let mut config = Config::for_tests(Path::new("/site/out"));
+        config
+            .author
+            .links
+            .push(String::from("https://twitter.com/jo/"));
+        let social = social("title: Page", None, &config);
+        assert_eq!(social.twitter.as_deref(), Some("@jo"));
+        assert!(social
+            .html()
+            .contains("<meta name=\"twitter:creator\" content=\"@jo\">"));
+    }
+}
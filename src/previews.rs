@@ -0,0 +1,266 @@
+//! Preview images for posts which don't have one of their own, so every post
+//! gets a large card when it's shared. The site supplies an SVG template,
+//! which is filled in with the post's title and subtitle and the site's name,
+//! then rendered to a PNG with the fonts bundled with the site.
+//!
+//! Rendering is slow, so every image is cached (by a hash of the filled-in
+//! template and the fonts) in the site's `.cache/previews`, and only copied to
+//! the output on later builds.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use minijinja::{context, AutoEscape, Environment};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::page::markdown::text::strip_tags;
+use crate::page::Page;
+
+/// Whether an image made it to the output.
+type Written = Result<(), String>;
+
+pub(crate) struct Previews<'c> {
+    config: &'c Config,
+    template: String,
+    env: Environment<'static>,
+    options: Options<'static>,
+    /// A hash of every font, so changing them regenerates the images.
+    fonts_hash: Vec<u8>,
+    output_dir: PathBuf,
+    cache_dir: PathBuf,
+    /// Each image is written once, however many posts share it, even when
+    /// they're generated at the same time.
+    generated: Mutex<HashMap<String, Arc<OnceLock<Written>>>>,
+}
+
+impl<'c> Previews<'c> {
+    /// Load the template and fonts, or `None` if the site has no template.
+    pub(crate) fn load(
+        config: &'c Config,
+        site_dir: &Path,
+    ) -> Result<Option<Previews<'c>>, String> {
+        let template_path = site_dir.join(&config.previews.template);
+        if !template_path.exists() {
+            return Ok(None);
+        }
+        let template = std::fs::read_to_string(&template_path)
+            .map_err(|e| format!("could not read '{}': {}", template_path.display(), e))?;
+
+        let fonts_dir = site_dir.join(&config.previews.fonts);
+        let mut font_paths = match std::fs::read_dir(&fonts_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|extension| extension.to_str())
+                        .is_some_and(|extension| {
+                            ["ttf", "otf", "ttc", "otc"]
+                                .contains(&extension.to_lowercase().as_str())
+                        })
+                })
+                .collect::<Vec<_>>(),
+            Err(_) => Vec::new(),
+        };
+        font_paths.sort();
+
+        let mut fonts = fontdb::Database::new();
+        let mut hasher = Sha256::new();
+        for path in &font_paths {
+            let data = std::fs::read(path)
+                .map_err(|e| format!("could not read '{}': {}", path.display(), e))?;
+            hasher.update(&data);
+            fonts.load_font_data(data);
+        }
+        if fonts.is_empty() {
+            eprintln!(
+                "warning: no fonts in '{}' for preview images, so they will have no text",
+                fonts_dir.display()
+            );
+        }
+
+        // Whatever the template is called, it's SVG.
+        let mut env = Environment::new();
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+
+        let options = Options {
+            fontdb: Arc::new(fonts),
+            ..Options::default()
+        };
+
+        Ok(Some(Previews {
+            config,
+            template,
+            env,
+            options,
+            fonts_hash: hasher.finalize().to_vec(),
+            output_dir: config.output.join(&config.previews.output_dir),
+            cache_dir: site_dir.join(".cache/previews"),
+            generated: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Generate the preview image for a page, returning its full URL.
+    pub(crate) fn generate(&self, page: &Page) -> Result<String, String> {
+        let svg = self.fill(&page.social.title, page.metadata.subtitle.as_deref())?;
+        let name = self.image(&svg)?;
+
+        let url_dir = self.config.previews.output_dir.to_string_lossy();
+        Ok(format!(
+            "{}/{}/{}",
+            self.config.url.trim_end_matches('/'),
+            url_dir.trim_matches('/'),
+            name
+        ))
+    }
+
+    /// The template, filled in with a page's title and subtitle.
+    fn fill(&self, title: &str, subtitle: Option<&str>) -> Result<String, String> {
+        let site = strip_tags(&self.config.title.stylized);
+        let subtitle = subtitle.map(strip_tags);
+        self.env
+            .render_str(
+                &self.template,
+                context! { title => title, subtitle => subtitle, site => site },
+            )
+            .map_err(|e| {
+                format!(
+                    "could not render '{}': {}",
+                    self.config.previews.template.display(),
+                    e
+                )
+            })
+    }
+
+    /// Put the image for `svg` in the output, rendering it only if it isn't
+    /// cached already, and return its file name.
+    fn image(&self, svg: &str) -> Result<String, String> {
+        let mut hasher = Sha256::new();
+        hasher.update(svg.as_bytes());
+        hasher.update(&self.fonts_hash);
+        let hash = hasher
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        let name = format!("{}.png", hash);
+
+        let cell = self
+            .generated
+            .lock()
+            .expect("no other thread panicked while holding the lock")
+            .entry(name.clone())
+            .or_default()
+            .clone();
+        cell.get_or_init(|| self.write(&name, svg)).clone()?;
+        Ok(name)
+    }
+
+    fn write(&self, name: &str, svg: &str) -> Written {
+        let cached = self.cache_dir.join(name);
+        if !cached.exists() {
+            let png = self
+                .render(svg)
+                .map_err(|e| format!("could not render preview image: {}", e))?;
+            std::fs::create_dir_all(&self.cache_dir)
+                .map_err(|e| format!("{}: {}", self.cache_dir.display(), e))?;
+            // Another build may be reading the cache at the same time, so the
+            // image only appears there once it's all written.
+            let partial = self
+                .cache_dir
+                .join(format!(".{}.{}", name, std::process::id()));
+            std::fs::write(&partial, png).map_err(|e| format!("{}: {}", partial.display(), e))?;
+            std::fs::rename(&partial, &cached)
+                .map_err(|e| format!("{}: {}", cached.display(), e))?;
+        }
+
+        std::fs::create_dir_all(&self.output_dir)
+            .map_err(|e| format!("{}: {}", self.output_dir.display(), e))?;
+        let output = self.output_dir.join(name);
+        std::fs::copy(&cached, &output).map_err(|e| format!("{}: {}", output.display(), e))?;
+        Ok(())
+    }
+
+    fn render(&self, svg: &str) -> Result<Vec<u8>, String> {
+        let tree = Tree::from_str(svg, &self.options).map_err(|e| e.to_string())?;
+        let size = tree.size().to_int_size();
+        let mut pixmap = Pixmap::new(size.width(), size.height())
+            .ok_or_else(|| format!("bad size {}×{}", size.width(), size.height()))?;
+        resvg::render(&tree, Transform::default(), &mut pixmap.as_mut());
+        pixmap.encode_png().map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A site with nothing but a preview template, somewhere of its own.
+    fn site(name: &str, template: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lx-previews-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("_ui")).expect("can create the test site");
+        std::fs::write(dir.join("_ui/preview.svg"), template).expect("can write the template");
+        dir
+    }
+
+    #[test]
+    fn fills_and_escapes_the_template() {
+        let dir = site(
+            "fill",
+            "<text>{{ title }}|{% if subtitle %}{{ subtitle }}|{% endif %}{{ site }}</text>",
+        );
+        let config = Config::for_tests(&dir.join("out"));
+        let previews = Previews::load(&config, &dir)
+            .expect("loads")
+            .expect("has a template");
+
+        assert_eq!(
+            previews.fill("Tom & Jerry <3", Some("<em>Cat</em> & mouse")),
+            Ok(String::from(
+                "<text>Tom &amp; Jerry &lt;3|Cat &amp; mouse|Example</text>"
+            ))
+        );
+        assert_eq!(
+            previews.fill("Untitled", None),
+            Ok(String::from("<text>Untitled|Example</text>"))
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn renders_once_and_then_uses_the_cache() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2"><rect width="4" height="2" fill="red"/></svg>"#;
+        let dir = site("cache", svg);
+        let config = Config::for_tests(&dir.join("out"));
+        let previews = Previews::load(&config, &dir)
+            .expect("loads")
+            .expect("has a template");
+
+        let name = previews.image(svg).expect("renders");
+        let cached = std::fs::read(dir.join(".cache/previews").join(&name)).expect("is cached");
+        assert!(cached.starts_with(b"\x89PNG"));
+        assert_eq!(
+            std::fs::read(dir.join("out/previews").join(&name)).ok(),
+            Some(cached)
+        );
+
+        // A later build copies whatever is in the cache, without rendering.
+        std::fs::write(dir.join(".cache/previews").join(&name), "cached").expect("can write");
+        let later = Previews::load(&config, &dir)
+            .expect("loads")
+            .expect("has a template");
+        assert_eq!(later.image(svg), Ok(name.clone()));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out/previews").join(&name)).ok(),
+            Some(String::from("cached"))
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,446 @@
+//! Redirects, in [Netlify's `_redirects` format][netlify]: the site's own
+//! hand-written rules, plus one for every old URL a page lists in its
+//! `aliases`, pointing to where the page lives now.
+//!
+//! For hosts which don't support redirects, the build can also write a stub
+//! page at each alias which sends browsers on with a meta refresh.
+//!
+//! [netlify]: https://docs.netlify.com/routing/redirects/
+
+pub(crate) mod check;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+
+use crate::config::Config;
+use crate::page::Page;
+
+/// A single redirect rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Rule {
+    /// The path (or, for host-specific rules, URL) to redirect from. May end
+    /// with a `*` splat and contain `:placeholder` segments.
+    pub(crate) from: String,
+    /// Query parameters the request must have, like `id=:id`.
+    pub(crate) query: Vec<String>,
+    pub(crate) to: String,
+    pub(crate) status: u16,
+    /// Whether to redirect even when there is a file at `from`.
+    pub(crate) force: bool,
+    /// Conditions like `Country=us` or `Role=admin`.
+    pub(crate) conditions: Vec<String>,
+    /// Where the rule came from: the line of a `_redirects` file, or the page
+    /// with the alias.
+    pub(crate) origin: String,
+}
+
+impl Rule {
+    /// Whether the rule matches exactly one path, so we can reason about it.
+    pub(crate) fn is_exact(&self) -> bool {
+        self.from.starts_with('/')
+            && !self.from.contains('*')
+            && !self.from.split('/').any(|segment| segment.starts_with(':'))
+            && self.query.is_empty()
+            && self.conditions.is_empty()
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.from)?;
+        for parameter in &self.query {
+            write!(f, " {}", parameter)?;
+        }
+        write!(f, " {} {}", self.to, self.status)?;
+        if self.force {
+            write!(f, "!")?;
+        }
+        for condition in &self.conditions {
+            write!(f, " {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the rules in a `_redirects` file. `name` is used to say where each
+/// rule came from.
+pub(crate) fn parse(contents: &str, name: &str) -> Result<Vec<Rule>, String> {
+    let mut rules = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let origin = format!("{}:{}", name, index + 1);
+        let mut tokens = line.split_whitespace().peekable();
+        let from = tokens.next().expect("the line is not empty").to_string();
+
+        let mut query = Vec::new();
+        while let Some(token) = tokens.peek() {
+            if token.contains('=') && !token.starts_with('/') && !token.contains("://") {
+                query.push(token.to_string());
+                tokens.next();
+            } else {
+                break;
+            }
+        }
+
+        let to = tokens
+            .next()
+            .ok_or_else(|| format!("{}: '{}' has no target", origin, from))?
+            .to_string();
+
+        let (status, force) = match tokens.peek() {
+            Some(token) if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                let (digits, force) = match token.strip_suffix('!') {
+                    Some(digits) => (digits, true),
+                    None => (*token, false),
+                };
+                let status = digits
+                    .parse::<u16>()
+                    .map_err(|_| format!("{}: bad status code '{}'", origin, token))?;
+                tokens.next();
+                (status, force)
+            }
+            _ => (301, false),
+        };
+
+        let conditions = tokens.map(String::from).collect();
+
+        rules.push(Rule {
+            from,
+            query,
+            to,
+            status,
+            force,
+            conditions,
+            origin,
+        });
+    }
+
+    Ok(rules)
+}
+
+/// Compare paths without caring about trailing slashes, which Netlify ignores.
+pub(crate) fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// Every cycle in the exact redirects, `by_from` their normalized paths.
+pub(crate) fn loops(by_from: &HashMap<&str, &Rule>) -> Vec<String> {
+    let mut starts = by_from.keys().copied().collect::<Vec<_>>();
+    starts.sort_unstable();
+
+    let mut loops = Vec::new();
+    let mut reported = Vec::<&str>::new();
+    for start in starts {
+        let mut chain = vec![start];
+        let mut current = start;
+        while let Some(rule) = by_from.get(current) {
+            let next = normalize(&rule.to);
+            if let Some(position) = chain.iter().position(|seen| *seen == next) {
+                let cycle = &chain[position..];
+                if !cycle.iter().any(|path| reported.contains(path)) {
+                    reported.extend(cycle);
+                    loops.push(format!("redirect loop: {} -> {}", cycle.join(" -> "), next));
+                }
+                break;
+            }
+            chain.push(next);
+            current = next;
+        }
+    }
+    loops
+}
+
+/// The site's redirects: its hand-written rules, then every page's aliases.
+pub(crate) struct Redirects {
+    /// The site's `_redirects` file, which is copied as is.
+    handwritten: String,
+    aliases: Vec<Rule>,
+}
+
+impl Redirects {
+    /// Collect the rules from the `_redirects` file in `site_dir`, if any, and
+    /// the aliases of `pages`, checking that they don't conflict with each
+    /// other or with the pages, and that following them never goes in
+    /// circles.
+    pub(crate) fn new(
+        pages: &[Page],
+        config: &Config,
+        site_dir: &Path,
+    ) -> Result<Redirects, String> {
+        let path = site_dir.join("_redirects");
+        let handwritten = if path.is_file() {
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("could not read '{}': {}", path.display(), e))?
+        } else {
+            String::new()
+        };
+        let mut rules = parse(&handwritten, "_redirects")?;
+        let written = rules.len();
+
+        let mut pages_by_url = HashMap::new();
+        for page in pages {
+            pages_by_url.insert(format!("/{}", page.metadata.slug), page);
+        }
+
+        for page in pages {
+            let to = format!("/{}", page.metadata.slug);
+            for alias in &page.metadata.aliases {
+                let from = if alias.starts_with('/') {
+                    alias.clone()
+                } else {
+                    format!("/{}", alias)
+                };
+                rules.push(Rule {
+                    from,
+                    query: Vec::new(),
+                    to: to.clone(),
+                    status: config.redirects.status,
+                    force: false,
+                    conditions: Vec::new(),
+                    origin: page.source.display().to_string(),
+                });
+            }
+        }
+
+        let mut errors = Vec::new();
+
+        // An alias must agree with any earlier rule for the same path, and
+        // may not be the URL of a page: the page would hide it. Hand-written
+        // rules can disagree among themselves: the host uses the first.
+        let mut by_from: HashMap<&str, &Rule> = HashMap::new();
+        for (index, rule) in rules.iter().enumerate() {
+            if !rule.is_exact() {
+                continue;
+            }
+
+            let from = normalize(&rule.from);
+            if index >= written {
+                if let Some(page) = pages_by_url.get(from) {
+                    errors.push(format!(
+                        "{}: alias '{}' is the URL of '{}'",
+                        rule.origin,
+                        rule.from,
+                        page.source.display()
+                    ));
+                }
+            }
+
+            match by_from.get(from) {
+                Some(existing)
+                    if index >= written && normalize(&existing.to) != normalize(&rule.to) =>
+                {
+                    errors.push(format!(
+                        "{}: '{}' redirects to '{}', but {} redirects it to '{}'",
+                        rule.origin, rule.from, rule.to, existing.origin, existing.to
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    by_from.insert(from, rule);
+                }
+            }
+        }
+
+        // Following redirects must always end up somewhere.
+        errors.extend(loops(&by_from));
+
+        if errors.is_empty() {
+            Ok(Redirects {
+                handwritten,
+                aliases: rules.split_off(written),
+            })
+        } else {
+            errors.sort();
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// Write `_redirects` to `output_dir`, and (if configured) a stub page for
+    /// each alias.
+    pub(crate) fn write(&self, config: &Config) -> Result<(), String> {
+        if self.handwritten.is_empty() && self.aliases.is_empty() {
+            return Ok(());
+        }
+
+        let mut contents = self.handwritten.clone();
+        if !self.aliases.is_empty() {
+            if !contents.is_empty() {
+                if !contents.ends_with('\n') {
+                    contents.push('\n');
+                }
+                contents.push('\n');
+            }
+            contents.push_str("# Generated from page aliases\n");
+            for rule in &self.aliases {
+                contents.push_str(&format!("{}\n", rule));
+            }
+        }
+
+        let path = config.output.join("_redirects");
+        std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        if config.redirects.stubs {
+            for rule in &self.aliases {
+                let path = stub_path(&config.output, &rule.from);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("{}: {}", parent.display(), e))?;
+                }
+                std::fs::write(&path, stub(&rule.to))
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a stub page must go to be served at `from`: pages are served from
+/// `.html` files without the extension, and paths ending in `/` from
+/// `index.html` in the directory.
+fn stub_path(output_dir: &Path, from: &str) -> PathBuf {
+    let relative = from.trim_start_matches('/');
+    if relative.is_empty() || relative.ends_with('/') {
+        output_dir.join(relative).join("index.html")
+    } else {
+        output_dir.join(format!("{}.html", relative))
+    }
+}
+
+fn stub(to: &str) -> String {
+    let mut href = String::new();
+    escape_href(&mut href, to).expect("writing to a String cannot fail");
+    let mut text = String::new();
+    escape_html(&mut text, to).expect("writing to a String cannot fail");
+
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Redirecting…</title>\n\
+         <link rel=\"canonical\" href=\"{href}\">\n\
+         <meta http-equiv=\"refresh\" content=\"0; url={href}\">\n\
+         <meta name=\"robots\" content=\"noindex\">\n\
+         </head>\n\
+         <body>\n\
+         <p>This page has moved to <a href=\"{href}\">{text}</a>.</p>\n\
+         </body>\n\
+         </html>\n",
+        href = href,
+        text = text
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A page at `path` in a site with no directory data, with `aliases`.
+    fn page(path: &str, aliases: &[&str], config: &Config) -> Page {
+        Page::for_tests(
+            path,
+            &format!("title: Page\naliases: {:?}", aliases),
+            config,
+        )
+    }
+
+    fn check(pages: &[Page], config: &Config) -> Result<(), String> {
+        Redirects::new(pages, config, Path::new("/site/nowhere")).map(|_| ())
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules = parse(
+            "# comment\n/old id=:id /new/:id 302! Country=us\n/a /b\n",
+            "r",
+        )
+        .expect("parses");
+        assert_eq!(rules[0].to_string(), "/old id=:id /new/:id 302! Country=us");
+        assert_eq!(rules[0].origin, "r:2");
+        assert!(!rules[0].is_exact());
+        assert_eq!((rules[1].status, rules[1].force), (301, false));
+        assert!(rules[1].is_exact());
+
+        assert_eq!(
+            parse("/a /b 30x", "r"),
+            Err(String::from("r:1: bad status code '30x'"))
+        );
+    }
+
+    #[test]
+    fn aliases_become_rules() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        let pages = [page("new.md", &["old", "/older/"], &config)];
+        let redirects =
+            Redirects::new(&pages, &config, Path::new("/site/nowhere")).expect("has no conflicts");
+        assert_eq!(
+            redirects
+                .aliases
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["/old /new 301", "/older/ /new 301"]
+        );
+    }
+
+    #[test]
+    fn two_pages_claiming_one_url() {
+        let config = Config::for_tests(Path::new("/site/out"));
+        let pages = [
+            page("a.md", &["/old"], &config),
+            page("b.md", &["/old/"], &config),
+        ];
+        assert_eq!(
+            check(&pages, &config),
+            Err(String::from(
+                "/site/content/b.md: '/old/' redirects to '/b', \
+                 but /site/content/a.md redirects it to '/a'"
+            ))
+        );
+
+        let pages = [page("a.md", &[], &config), page("b.md", &["a"], &config)];
+        assert_eq!(
+            check(&pages, &config),
+            Err(String::from(
+                "/site/content/b.md: alias '/a' is the URL of '/site/content/a.md'"
+            ))
+        );
+    }
+
+    #[test]
+    fn redirect_loops() {
+        let rules = parse("/a /b\n/b /c\n/c /a/\n/d /a\n", "r").expect("parses");
+        let by_from = rules
+            .iter()
+            .map(|rule| (normalize(&rule.from), rule))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            loops(&by_from),
+            vec![String::from("redirect loop: /a -> /b -> /c -> /a")]
+        );
+    }
+
+    #[test]
+    fn stub_pages() {
+        assert_eq!(
+            stub_path(Path::new("/out"), "/old/"),
+            PathBuf::from("/out/old/index.html")
+        );
+        assert_eq!(
+            stub_path(Path::new("/out"), "/old"),
+            PathBuf::from("/out/old.html")
+        );
+        assert!(stub("/a?b=1&c=2").contains("url=/a?b=1&amp;c=2\""));
+    }
+}
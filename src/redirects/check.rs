@@ -0,0 +1,335 @@
+//! `lx check-redirects`: find the mistakes in a `_redirects` file which the
+//! host would silently accept. Rules are checked in order, as Netlify applies
+//! them: the first rule matching a request wins.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, FixedOffset, Local};
+use rayon::iter::Either;
+use rayon::prelude::*;
+use regex::Regex;
+
+use crate::assets::Manifest;
+use crate::build::{get_files_to_load, BuildOptions};
+use crate::config::Config;
+use crate::history::History;
+use crate::page::{Page, Source};
+
+use super::{loops, normalize, parse, Rule};
+
+/// Check the redirects in `file` (by default, the site's own `_redirects`)
+/// against the pages and assets the site in `in_dir` builds with `options`,
+/// printing every problem found.
+pub fn check_redirects(
+    in_dir: PathBuf,
+    file: Option<PathBuf>,
+    options: BuildOptions,
+) -> Result<(), String> {
+    let as_of = options.as_of.unwrap_or_else(|| Local::now().into());
+    let in_dir = std::fs::canonicalize(in_dir).map_err(|e| e.to_string())?;
+    let config = Config::from_file(&in_dir.join("_data/config.json5"))?;
+
+    let file = file.unwrap_or_else(|| in_dir.join("_redirects"));
+    let contents = std::fs::read_to_string(&file)
+        .map_err(|e| format!("could not read '{}': {}", file.display(), e))?;
+    let name = file.display().to_string();
+    let rules = parse(&contents, &name)?;
+
+    let urls = site_urls(&in_dir, &config, options.drafts, &as_of)?;
+    let site = config.url.trim_end_matches('/');
+    // The path of a URL on this site, or `None` for anywhere else.
+    let local = |url: &str| -> Option<String> {
+        let path = if url.starts_with('/') {
+            url
+        } else {
+            match url.strip_prefix(site)? {
+                "" => "/",
+                path => path,
+            }
+        };
+        let end = path.find(&['?', '#'][..]).unwrap_or(path.len());
+        Some(normalize(&path[..end]).to_string())
+    };
+
+    let mut problems = Vec::new();
+
+    // Only the first of several rules for the same path is ever used, and
+    // none at all if there's a file there and the rule isn't forced.
+    let exact = rules
+        .iter()
+        .filter(|rule| rule.is_exact() && (rule.force || !urls.contains(normalize(&rule.from))))
+        .map(|rule| (normalize(&rule.from), rule))
+        .collect::<Vec<_>>();
+    let mut by_from: HashMap<&str, &Rule> = HashMap::new();
+    for (from, rule) in &exact {
+        by_from.entry(from).or_insert(rule);
+    }
+
+    for (index, rule) in rules.iter().enumerate() {
+        if let Some(earlier) = rules[..index].iter().find(|earlier| shadows(earlier, rule)) {
+            problems.push(format!(
+                "{}: '{}' is never used, because {} ('{}') matches it first",
+                rule.origin, rule.from, earlier.origin, earlier.from
+            ));
+            continue;
+        }
+
+        if rule.is_exact() && !rule.force && urls.contains(normalize(&rule.from)) {
+            problems.push(format!(
+                "{}: '{}' is never used, because the build writes a file there \
+                 (add a `!` to the status to force it)",
+                rule.origin, rule.from
+            ));
+        }
+
+        let to = match local(&rule.to) {
+            Some(to) => to,
+            None => continue,
+        };
+
+        if let Some(next) = by_from.get(to.as_str()) {
+            // Loops are reported all together below.
+            if !loops_back(&to, &by_from) {
+                problems.push(format!(
+                    "{}: '{}' redirects to '{}', which {} redirects again to '{}'",
+                    rule.origin, rule.from, rule.to, next.origin, next.to
+                ));
+            }
+            continue;
+        }
+
+        if !resolves(&to, &urls) {
+            problems.push(format!(
+                "{}: '{}' redirects to '{}', which the build does not produce",
+                rule.origin, rule.from, rule.to
+            ));
+        }
+    }
+
+    problems.extend(loops(&by_from));
+
+    if problems.is_empty() {
+        println!("{}: {} rules, all fine", name, rules.len());
+        Ok(())
+    } else {
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        Err(format!(
+            "found {} problem{} in '{}'",
+            problems.len(),
+            if problems.len() == 1 { "" } else { "s" },
+            name
+        ))
+    }
+}
+
+/// Whether following the redirects from `path` ever comes back around.
+fn loops_back(path: &str, by_from: &HashMap<&str, &Rule>) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = path;
+    while let Some(rule) = by_from.get(current) {
+        if !seen.insert(current) {
+            return true;
+        }
+        current = normalize(&rule.to);
+    }
+    false
+}
+
+/// Every path the build writes a page or asset to, normalized: the pages the
+/// build publishes (drafts and scheduled pages only with `drafts`) and the
+/// assets the site has, plus the generated files in the output directory from
+/// the last build, which is the only place to find what's made along the way
+/// (compiled styles, image variants, previews, the sitemap, and so on). Pages
+/// in the output directory don't count: they may be left over from a build
+/// with drafts, or of a page which is gone now.
+fn site_urls(
+    in_dir: &Path,
+    config: &Config,
+    drafts: bool,
+    as_of: &DateTime<FixedOffset>,
+) -> Result<HashSet<String>, String> {
+    let content_dir = in_dir.join("content");
+    let history = History::load(&config.git, in_dir)?;
+    let (slugs, errors): (Vec<Option<String>>, Vec<String>) = get_files_to_load(in_dir)
+        .into_par_iter()
+        .map(|path| {
+            let contents =
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Page::metadata_for(
+                &Source {
+                    path: path.clone(),
+                    contents,
+                },
+                &content_dir,
+                config,
+                history.dates(&path),
+            )
+            .map(|metadata| (drafts || metadata.is_published(as_of)).then_some(metadata.slug))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+        })
+        .partition_map(|result| match result {
+            Ok(slug) => Either::Left(slug),
+            Err(e) => Either::Right(e),
+        });
+
+    // A page which won't build has no URL, which may well be the problem.
+    for error in errors {
+        eprintln!("problem with {}", error);
+    }
+
+    let mut urls = HashSet::new();
+    if config.archive.enabled {
+        urls.insert(format!("/{}", config.archive.slug.trim_matches('/')));
+    }
+    for slug in slugs.into_iter().flatten() {
+        // Hosts serve `foo/index.html` at `foo/`.
+        if slug == "index" || slug.ends_with("/index") {
+            urls.insert(normalize(&format!("/{}", slug.trim_end_matches("index"))).to_string());
+        }
+        urls.insert(format!("/{}", slug));
+    }
+
+    let assets = Manifest::new(&config.assets, in_dir)?;
+    urls.extend(assets.urls().values().map(|url| normalize(url).to_string()));
+
+    urls.extend(built_urls(&config.output)?);
+    if !config.output.is_dir() {
+        eprintln!(
+            "warning: '{}' has not been built, so generated files will look missing",
+            config.output.display()
+        );
+    }
+
+    Ok(urls)
+}
+
+/// The path of every generated file in the built site at `output_dir`: all
+/// but the pages, which come from the sources, and the host's own
+/// configuration files, which aren't served at all.
+fn built_urls(output_dir: &Path) -> Result<HashSet<String>, String> {
+    let pattern = output_dir.join("**/*");
+    let pattern = pattern.to_string_lossy();
+    let mut urls = HashSet::new();
+    for path in glob::glob(&pattern)
+        .map_err(|e| format!("bad glob '{}': {}", pattern, e))?
+        .filter_map(Result::ok)
+        .filter(|path| path.is_file())
+    {
+        let relative = match path.strip_prefix(output_dir) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        if relative == "_redirects" || relative == "_headers" {
+            continue;
+        }
+
+        if relative.ends_with(".html") {
+            continue;
+        }
+
+        urls.insert(format!("/{}", relative));
+    }
+    Ok(urls)
+}
+
+/// Whether `path` is somewhere the build writes to. For paths with a splat or
+/// placeholders, that means anything at all beneath the part before them.
+fn resolves(path: &str, urls: &HashSet<String>) -> bool {
+    if urls.contains(path) {
+        return true;
+    }
+
+    let dynamic = path.find('*').into_iter().chain(path.find("/:")).min();
+    match dynamic {
+        Some(index) => {
+            let prefix = &path[..index];
+            urls.iter().any(|url| url.starts_with(prefix))
+        }
+        None => false,
+    }
+}
+
+/// Whether every request `later` matches is matched by `earlier` first.
+fn shadows(earlier: &Rule, later: &Rule) -> bool {
+    if earlier.query != later.query || earlier.conditions != later.conditions {
+        return false;
+    }
+
+    let pattern = normalize(&earlier.from)
+        .split('/')
+        .map(|segment| {
+            if segment.starts_with(':') {
+                String::from("[^/]+")
+            } else {
+                regex::escape(segment).replace(r"\*", ".*")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    Regex::new(&format!("^{}/?$", pattern))
+        .map(|pattern| pattern.is_match(normalize(&later.from)))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A site with a published page, a draft, and a scheduled page, built at
+    /// some point with drafts, somewhere of its own.
+    fn site() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lx-check-redirects-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in [
+            ("content/page.md", "---\ntitle: Page\n---\n\nHi.\n"),
+            (
+                "content/draft.md",
+                "---\ntitle: Draft\ndraft: true\n---\n\nHi.\n",
+            ),
+            (
+                "content/later.md",
+                "---\ntitle: Later\ndate: 2030-01-01T09:00:00-07:00\n---\n\nHi.\n",
+            ),
+            ("out/page.html", ""),
+            ("out/draft.html", ""),
+            ("out/gone/index.html", ""),
+            ("out/images/photo-480.webp", ""),
+            ("out/_redirects", ""),
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().expect("has a parent")).expect("can create");
+            std::fs::write(path, contents).expect("can write");
+        }
+        dir
+    }
+
+    #[test]
+    fn only_published_pages_and_generated_files_count() {
+        let dir = site();
+        let config = Config::for_tests(&dir.join("out"));
+        let as_of = DateTime::parse_from_rfc3339("2026-10-19T00:00:00Z").expect("is a date");
+
+        let urls = site_urls(&dir, &config, false, &as_of).expect("finds the urls");
+        assert!(urls.contains("/page"));
+        assert!(urls.contains("/images/photo-480.webp"));
+        for missing in ["/draft", "/later", "/gone", "/gone/index", "/_redirects"] {
+            assert!(!urls.contains(missing), "{} should not count", missing);
+        }
+
+        let urls = site_urls(&dir, &config, true, &as_of).expect("finds the urls");
+        assert!(urls.contains("/draft"));
+        assert!(urls.contains("/later"));
+        assert!(!urls.contains("/gone"));
+
+        let later = DateTime::parse_from_rfc3339("2030-01-02T00:00:00Z").expect("is a date");
+        let urls = site_urls(&dir, &config, false, &later).expect("finds the urls");
+        assert!(urls.contains("/later"));
+        assert!(!urls.contains("/draft"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,148 @@
+//! Full-text search without a search service: the build writes an inverted
+//! index of every page's title, tags, summary, and text to the output
+//! directory, and a small script searches it in the browser.
+//!
+//! The index is split into shards by the first character of each word, so a
+//! search only fetches the shards for the words it's looking for. Alongside
+//! them, `index.json` lists the pages (by number, which is how the shards
+//! refer to them), the shards, and the words left out.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_derive::Serialize;
+
+use crate::config::Config;
+use crate::page::Page;
+
+/// The script which searches the index, written alongside it.
+const SCRIPT: &str = include_str!("search/search.js");
+
+/// How much a word counts in each part of a page.
+const TITLE_WEIGHT: u32 = 10;
+const TAG_WEIGHT: u32 = 5;
+const SUMMARY_WEIGHT: u32 = 2;
+const TEXT_WEIGHT: u32 = 1;
+
+#[derive(Serialize, Debug)]
+struct Document {
+    url: String,
+    title: Option<String>,
+    summary: Option<String>,
+}
+
+/// Every word, by shard, with the pages it appears in and how much it counts
+/// there.
+type Shards = BTreeMap<String, BTreeMap<String, Vec<(usize, u32)>>>;
+
+pub(crate) struct Index {
+    documents: Vec<Document>,
+    shards: Shards,
+}
+
+impl Index {
+    /// Index every page which hasn't opted out with `search: false`.
+    pub(crate) fn new(pages: &[Page], config: &Config) -> Index {
+        let stop_words = config
+            .search
+            .stop_words
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<HashSet<_>>();
+
+        let mut pages = pages
+            .iter()
+            .filter(|page| page.metadata.search)
+            .collect::<Vec<_>>();
+        pages.sort_by(|a, b| a.metadata.slug.cmp(&b.metadata.slug));
+
+        let mut documents = Vec::with_capacity(pages.len());
+        let mut shards = Shards::new();
+        for (number, page) in pages.into_iter().enumerate() {
+            let mut scores = HashMap::<String, u32>::new();
+            let mut add = |text: &str, weight: u32| {
+                for term in terms(text, &stop_words) {
+                    *scores.entry(term).or_default() += weight;
+                }
+            };
+            if let Some(title) = page.metadata.title() {
+                add(title, TITLE_WEIGHT);
+            }
+            for tag in &page.metadata.tags {
+                add(tag, TAG_WEIGHT);
+            }
+            if let Some(summary) = &page.summary {
+                add(&summary.text, SUMMARY_WEIGHT);
+            }
+            add(&page.text, TEXT_WEIGHT);
+
+            for (term, score) in scores {
+                shards
+                    .entry(shard_key(&term))
+                    .or_default()
+                    .entry(term)
+                    .or_default()
+                    .push((number, score));
+            }
+
+            documents.push(Document {
+                url: format!("/{}", page.metadata.slug),
+                title: page.metadata.title().map(String::from),
+                summary: page.summary.as_ref().map(|summary| summary.text.clone()),
+            });
+        }
+
+        Index { documents, shards }
+    }
+
+    /// Write the index, its shards, and the script to search them.
+    pub(crate) fn write(&self, config: &Config) -> Result<(), String> {
+        let dir = config.output.join(&config.search.output_dir);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let write = |name: &str, contents: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+        };
+
+        let mut stop_words = config
+            .search
+            .stop_words
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<_>>();
+        stop_words.sort();
+        stop_words.dedup();
+        let index = serde_json::json!({
+            "documents": self.documents,
+            "shards": self.shards.keys().collect::<Vec<_>>(),
+            "stop_words": stop_words,
+        });
+        write("index.json", index.to_string())?;
+
+        for (key, terms) in &self.shards {
+            let json = serde_json::to_string(terms)
+                .map_err(|e| format!("could not serialize search shard '{}': {}", key, e))?;
+            write(&format!("{}.json", key), json)?;
+        }
+
+        write("search.js", SCRIPT.to_string())
+    }
+}
+
+/// The words worth indexing in some text, lowercased. The script must split
+/// queries the same way.
+fn terms<'t>(text: &'t str, stop_words: &'t HashSet<String>) -> impl Iterator<Item = String> + 't {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().nth(1).is_some())
+        .map(str::to_lowercase)
+        .filter(move |word| !stop_words.contains(word))
+}
+
+/// Which shard a word goes in: its first character, if that's a plain ASCII
+/// letter or digit, or else that character's code point.
+fn shard_key(term: &str) -> String {
+    match term.chars().next() {
+        Some(c) if c.is_ascii_alphanumeric() => c.to_string(),
+        Some(c) => format!("u{:04x}", u32::from(c)),
+        None => String::from("_"),
+    }
+}
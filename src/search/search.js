@@ -0,0 +1,88 @@
+// Search the index `lx build` writes alongside this script:
+//
+//     import { search } from "/search/search.js";
+//     const results = await search("some words");
+//
+// Each result is `{ url, title, summary, score }`, best first. A page only
+// matches if it has every word in the query, or a word starting with it, so
+// results narrow as someone types.
+
+const base = new URL(".", import.meta.url);
+const shards = new Map();
+let index = null;
+
+function load(name) {
+  return fetch(new URL(name, base)).then((response) => {
+    if (!response.ok) {
+      throw new Error(`could not load search index '${name}': ${response.status}`);
+    }
+    return response.json();
+  });
+}
+
+// Split text into words exactly as the build does.
+function terms(text, stopWords) {
+  return text
+    .toLowerCase()
+    .split(/[^\p{Alphabetic}\p{N}]+/u)
+    .filter((word) => [...word].length > 1 && !stopWords.has(word));
+}
+
+function shardKey(term) {
+  const codePoint = term.codePointAt(0);
+  const first = String.fromCodePoint(codePoint);
+  return /^[a-z0-9]$/.test(first) ? first : "u" + codePoint.toString(16).padStart(4, "0");
+}
+
+function shard(key) {
+  if (!shards.has(key)) {
+    shards.set(key, load(`${key}.json`));
+  }
+  return shards.get(key);
+}
+
+export async function search(query, { limit = 20 } = {}) {
+  index ??= load("index.json").then((loaded) => ({
+    documents: loaded.documents,
+    shards: new Set(loaded.shards),
+    stopWords: new Set(loaded.stop_words),
+  }));
+  const { documents, shards: available, stopWords } = await index;
+
+  const words = [...new Set(terms(query, stopWords))];
+  if (words.length === 0) {
+    return [];
+  }
+
+  let totals = null;
+  for (const word of words) {
+    const key = shardKey(word);
+    const scores = new Map();
+    if (available.has(key)) {
+      for (const [term, postings] of Object.entries(await shard(key))) {
+        if (!term.startsWith(word)) {
+          continue;
+        }
+        // Whole words count for more than the starts of longer ones.
+        const weight = term === word ? 1 : 0.5;
+        for (const [document, score] of postings) {
+          scores.set(document, Math.max(scores.get(document) ?? 0, score * weight));
+        }
+      }
+    }
+
+    totals =
+      totals === null
+        ? scores
+        : new Map(
+            [...totals]
+              .filter(([document]) => scores.has(document))
+              .map(([document, total]) => [document, total + scores.get(document)]),
+          );
+  }
+
+  return [...totals]
+    .sort(([, a], [, b]) => b - a)
+    .slice(0, limit)
+    .map(([document, score]) => ({ ...documents[document], score }));
+}
@@ -0,0 +1,248 @@
+//! The [sitemap] listing every page for search engines, and the `robots.txt`
+//! which tells them where to find it.
+//!
+//! A sitemap may only list 50,000 URLs, so bigger sites get several, numbered
+//! `sitemap-1.xml` and so on, and `sitemap.xml` is an index of them instead.
+//!
+//! [sitemap]: https://www.sitemaps.org/protocol.html
+
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+use minijinja::{context, Environment};
+use pulldown_cmark::escape::escape_html;
+
+use crate::assets::Manifest;
+use crate::config::Config;
+use crate::page::Page;
+
+/// The most URLs a single sitemap may list.
+const LIMIT: usize = 50_000;
+
+pub(crate) struct Sitemap {
+    /// Every page's URL, and when it last changed, by URL.
+    entries: Vec<(String, Option<DateTime<FixedOffset>>)>,
+}
+
+impl Sitemap {
+    /// List every page which hasn't opted out with `sitemap: false`.
+    pub(crate) fn new(pages: &[Page], config: &Config) -> Sitemap {
+        let mut entries = pages
+            .iter()
+            .filter(|page| page.metadata.sitemap)
+            .map(|page| {
+                let modified = page
+                    .metadata
+                    .updated
+                    .or_else(|| page.metadata.date().copied())
+                    .or(page.metadata.created);
+                (page.url(config), modified)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Sitemap { entries }
+    }
+
+    /// Write `sitemap.xml` (and, for big sites, the sitemaps it indexes).
+    pub(crate) fn write(&self, config: &Config) -> Result<(), String> {
+        let write = |name: &str, contents: String| {
+            let path = config.output.join(name);
+            std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+        };
+
+        if self.entries.len() <= LIMIT {
+            return write("sitemap.xml", urlset(&self.entries));
+        }
+
+        let base = config.url.trim_end_matches('/');
+        let mut index = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        for (number, chunk) in self.entries.chunks(LIMIT).enumerate() {
+            let name = format!("sitemap-{}.xml", number + 1);
+            write(&name, urlset(chunk))?;
+
+            index.push_str("  <sitemap>\n");
+            push_element(&mut index, "loc", &format!("{}/{}", base, name));
+            if let Some(modified) = chunk.iter().filter_map(|(_, modified)| *modified).max() {
+                push_element(&mut index, "lastmod", &modified.to_rfc3339());
+            }
+            index.push_str("  </sitemap>\n");
+        }
+        index.push_str("</sitemapindex>\n");
+        write("sitemap.xml", index)
+    }
+}
+
+fn urlset(entries: &[(String, Option<DateTime<FixedOffset>>)]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (url, modified) in entries {
+        xml.push_str("  <url>\n");
+        push_element(&mut xml, "loc", url);
+        if let Some(modified) = modified {
+            push_element(&mut xml, "lastmod", &modified.to_rfc3339());
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn push_element(xml: &mut String, name: &str, text: &str) {
+    xml.push_str(&format!("    <{}>", name));
+    escape_html(&mut *xml, text).expect("writing to a String cannot fail");
+    xml.push_str(&format!("</{}>\n", name));
+}
+
+/// Write `robots.txt`, unless the site has its own in `content` or `_static`.
+/// It is rendered from the template in `_ui/robots.txt`, with the sitemap's
+/// URL as `sitemap` and the site's as `url`; without a template, it allows
+/// everything and points to the sitemap.
+pub(crate) fn write_robots(
+    config: &Config,
+    site_dir: &Path,
+    assets: &Manifest,
+) -> Result<(), String> {
+    if assets.url("robots.txt").is_some() {
+        return Ok(());
+    }
+
+    let url = config.url.trim_end_matches('/');
+    let sitemap = format!("{}/sitemap.xml", url);
+
+    let template_path = site_dir.join("_ui/robots.txt");
+    let contents = if template_path.is_file() {
+        let template = std::fs::read_to_string(&template_path)
+            .map_err(|e| format!("could not read '{}': {}", template_path.display(), e))?;
+        let mut rendered = Environment::new()
+            .render_str(&template, context! { sitemap => &sitemap, url => url })
+            .map_err(|e| format!("could not render '{}': {}", template_path.display(), e))?;
+        if !rendered.ends_with('\n') {
+            rendered.push('\n');
+        }
+        rendered
+    } else {
+        format!("User-agent: *\nAllow: /\n\nSitemap: {}\n", sitemap)
+    };
+
+    let path = config.output.join("robots.txt");
+    std::fs::write(&path, contents).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn output(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lx-sitemap-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("can create the output");
+        dir
+    }
+
+    #[test]
+    fn lists_pages_which_have_not_opted_out() {
+        let dir = output("pages");
+        let config = Config::for_tests(&dir);
+        let pages = [
+            Page::for_tests(
+                "b.md",
+                "title: B\ndate: 2024-01-01T09:00:00Z\nupdated: 2024-02-01T09:00:00Z",
+                &config,
+            ),
+            Page::for_tests("hidden.md", "title: Hidden\nsitemap: false", &config),
+            Page::for_tests("a & b.md", "title: A", &config),
+        ];
+        Sitemap::new(&pages, &config)
+            .write(&config)
+            .expect("writes");
+
+        assert_eq!(
+            std::fs::read_to_string(dir.join("sitemap.xml")).ok(),
+            Some(String::from(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                 <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n\
+                 \x20 <url>\n\
+                 \x20   <loc>https://example.com/a-b</loc>\n\
+                 \x20 </url>\n\
+                 \x20 <url>\n\
+                 \x20   <loc>https://example.com/b</loc>\n\
+                 \x20   <lastmod>2024-02-01T09:00:00+00:00</lastmod>\n\
+                 \x20 </url>\n\
+                 </urlset>\n"
+            ))
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn big_sites_get_an_index() {
+        let dir = output("index");
+        let config = Config::for_tests(&dir);
+        let modified = DateTime::parse_from_rfc3339("2024-03-01T09:00:00Z").ok();
+        let entries = (0..=LIMIT)
+            .map(|number| (format!("https://example.com/{:06}", number), None))
+            .chain(std::iter::once((
+                String::from("https://example.com/zz"),
+                modified,
+            )))
+            .collect();
+        Sitemap { entries }.write(&config).expect("writes");
+
+        let index = std::fs::read_to_string(dir.join("sitemap.xml")).expect("is written");
+        assert!(index.contains("<sitemapindex"));
+        assert!(index.contains("<loc>https://example.com/sitemap-1.xml</loc>"));
+        assert!(index.contains(
+            "<loc>https://example.com/sitemap-2.xml</loc>\n    \
+             <lastmod>2024-03-01T09:00:00+00:00</lastmod>"
+        ));
+        assert!(!dir.join("sitemap-3.xml").exists());
+
+        let count = |name: &str| {
+            std::fs::read_to_string(dir.join(name))
+                .expect("is written")
+                .matches("<url>")
+                .count()
+        };
+        assert_eq!(count("sitemap-1.xml"), LIMIT);
+        assert_eq!(count("sitemap-2.xml"), 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn robots() {
+        let dir = output("robots");
+        let config = Config::for_tests(&dir.join("out"));
+        std::fs::create_dir_all(&config.output).expect("can create the output");
+        let assets = Manifest::new(&config.assets, &dir).expect("has no assets");
+
+        write_robots(&config, &dir, &assets).expect("writes");
+        assert_eq!(
+            std::fs::read_to_string(config.output.join("robots.txt")).ok(),
+            Some(String::from(
+                "User-agent: *\nAllow: /\n\nSitemap: https://example.com/sitemap.xml\n"
+            ))
+        );
+
+        std::fs::create_dir_all(dir.join("_ui")).expect("can create");
+        std::fs::write(
+            dir.join("_ui/robots.txt"),
+            "User-agent: *\nDisallow: /drafts/\nSitemap: {{ sitemap }}",
+        )
+        .expect("can write");
+        write_robots(&config, &dir, &assets).expect("writes");
+        assert_eq!(
+            std::fs::read_to_string(config.output.join("robots.txt")).ok(),
+            Some(String::from(
+                "User-agent: *\nDisallow: /drafts/\nSitemap: https://example.com/sitemap.xml\n"
+            ))
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,97 @@
+//! Stylesheets: every SCSS, Sass, or CSS file in the site's `_ui/styles`
+//! (other than partials, whose names start with `_`) is compiled to a single
+//! CSS file, with its `@import`s inlined. Plain CSS is valid SCSS, so CSS
+//! files can import each other too; leave off the extension (`@import "fonts"`)
+//! to inline the file rather than leave the `@import` for the browser.
+//!
+//! The compiled stylesheets are added to the asset manifest, so templates find
+//! them by their logical path (e.g. `styles/style.css`) like any other asset,
+//! and absolute `url()`s in them are pointed at the assets' real URLs.
+
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use regex::{Captures, Regex};
+
+use crate::assets::Manifest;
+use crate::config::Styles;
+
+lazy_static! {
+    /// A `url()` with an absolute path, which may be an asset.
+    static ref URL: Regex =
+        Regex::new(r#"url\(\s*(?P<quote>["']?)(?P<url>/[^"')\s]*)["']?\s*\)"#)
+            .expect("CSS url regex is legit");
+}
+
+/// Compile the stylesheets in `site_dir/_ui/styles`, and add them to `assets`.
+pub(crate) fn compile(
+    config: &Styles,
+    site_dir: &Path,
+    assets: &mut Manifest,
+) -> Result<(), String> {
+    let styles_dir = site_dir.join("_ui/styles");
+    if !styles_dir.is_dir() {
+        return Ok(());
+    }
+
+    let entries = std::fs::read_dir(&styles_dir)
+        .map_err(|e| format!("{}: {}", styles_dir.display(), e))?
+        .map(|entry| {
+            entry
+                .map(|entry| entry.path())
+                .map_err(|e| format!("{}: {}", styles_dir.display(), e))
+        })
+        .collect::<Result<Vec<PathBuf>, String>>()?
+        .into_iter()
+        .filter(|path| {
+            let is_stylesheet = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| matches!(extension, "scss" | "sass" | "css"));
+            let is_partial = path
+                .file_name()
+                .is_none_or(|name| name.to_string_lossy().starts_with(['_', '.']));
+            path.is_file() && is_stylesheet && !is_partial
+        })
+        .collect::<Vec<_>>();
+
+    let style = if config.minify {
+        grass::OutputStyle::Compressed
+    } else {
+        grass::OutputStyle::Expanded
+    };
+
+    let url_dir = config.output_dir.to_string_lossy();
+    let url_dir = url_dir.trim_matches('/');
+    let compiled = entries
+        .par_iter()
+        .map(|path| {
+            let options = grass::Options::default()
+                .style(style)
+                .load_path(&styles_dir);
+            let css = grass::from_path(path, &options)
+                .map_err(|e| format!("could not compile '{}': {}", path.display(), e))?;
+
+            let stem = path
+                .file_stem()
+                .expect("stylesheets have names")
+                .to_string_lossy();
+            Ok((format!("{}/{}.css", url_dir, stem), css))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    for (logical, css) in compiled {
+        let css = URL
+            .replace_all(&css, |captures: &Captures| {
+                match assets.resolve(&captures["url"], &styles_dir) {
+                    Some(url) => format!("url({0}{1}{0})", &captures["quote"], url),
+                    None => captures[0].to_string(),
+                }
+            })
+            .to_string();
+        assets.add(logical, css)?;
+    }
+
+    Ok(())
+}